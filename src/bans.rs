@@ -0,0 +1,176 @@
+//! Who may not chat with strangers. A ban applies everywhere or in one
+//! server, optionally until a given time, and is checked whenever someone
+//! starts looking for a partner.
+
+use std::time::Duration;
+
+use serenity::all::{Context, GuildId, UserId};
+
+use crate::error::GenericError;
+use crate::{expel, get_sessions, notify_user};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BanScope {
+    Global,
+    Guild(GuildId),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ban {
+    pub user: UserId,
+    pub scope: BanScope,
+    pub reason: Option<String>,
+    pub banned_by: UserId,
+    pub created_at: u64,
+    /// When the ban runs out (unix seconds); `None` for a permanent ban.
+    pub expires_at: Option<u64>,
+}
+
+impl Ban {
+    pub fn is_active(&self, now: u64) -> bool {
+        self.expires_at.is_none_or(|at| at > now)
+    }
+
+    /// Whether the ban keeps someone from chatting in `guild`.
+    pub fn applies_in(&self, guild: Option<GuildId>) -> bool {
+        match self.scope {
+            BanScope::Global => true,
+            BanScope::Guild(banned) => guild == Some(banned),
+        }
+    }
+
+    /// What the banned user is told.
+    pub fn refusal(&self) -> String {
+        let place = match self.scope {
+            BanScope::Global => "",
+            BanScope::Guild(_) => " in this server",
+        };
+        let mut refusal = match self.expires_at {
+            Some(at) => {
+                format!("You are banned from chatting with strangers{place} until <t:{at}:f>.")
+            }
+            None => format!("You are banned from chatting with strangers{place}."),
+        };
+        if let Some(reason) = &self.reason {
+            refusal.push_str(&format!("\nReason: {reason}"));
+        }
+        refusal
+    }
+
+    /// One line for the ban list.
+    pub fn describe(&self) -> String {
+        let until = match self.expires_at {
+            Some(at) => format!("until <t:{at}:f>"),
+            None => "permanent".to_string(),
+        };
+        let reason = self.reason.as_deref().unwrap_or("no reason given");
+        format!(
+            "<@{0}> ({0}), {until}, by <@{1}>: {reason}",
+            self.user, self.banned_by
+        )
+    }
+}
+
+/// Longest a ban can be given for; anything longer should be permanent.
+pub const MAX_DURATION: Duration = Duration::from_secs(10 * 365 * 24 * 60 * 60);
+
+/// A length like `30m`, `12h`, `7d` or `2w`, up to [`MAX_DURATION`].
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim().to_ascii_lowercase();
+    let split = value.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount.parse().ok().filter(|n| *n > 0)?;
+    let unit_secs = match unit.trim() {
+        "m" | "min" | "mins" | "minutes" => 60,
+        "h" | "hour" | "hours" => 60 * 60,
+        "d" | "day" | "days" => 24 * 60 * 60,
+        "w" | "week" | "weeks" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    amount
+        .checked_mul(unit_secs)
+        .map(Duration::from_secs)
+        .filter(|length| *length <= MAX_DURATION)
+}
+
+/// Stores `ban`, ends any chat it covers right away and tells the user.
+pub async fn impose(ctx: &Context, ban: &Ban) -> Result<(), GenericError> {
+    let sessions = get_sessions(ctx).await;
+    sessions.add_ban(ban).await?;
+    if let Some(user) = sessions.load_user(ban.user).await? {
+        if ban.applies_in(user.guild) {
+            expel(ctx, &user, &*sessions).await?;
+        }
+    }
+    notify_user(ctx, ban.user, &ban.refusal()).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ban(scope: BanScope, expires_at: Option<u64>) -> Ban {
+        Ban {
+            user: UserId::new(1),
+            scope,
+            reason: None,
+            banned_by: UserId::new(2),
+            created_at: 0,
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn durations_need_a_unit() {
+        assert_eq!(parse_duration("30m"), Some(Duration::from_secs(1800)));
+        assert_eq!(
+            parse_duration(" 2 Days"),
+            Some(Duration::from_secs(172_800))
+        );
+        assert_eq!(parse_duration("1w"), Some(Duration::from_secs(604_800)));
+        for invalid in ["", "7", "d", "0h", "-1d", "3y", "99999999999999999999d"] {
+            assert_eq!(parse_duration(invalid), None, "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn durations_stop_at_ten_years() {
+        assert_eq!(parse_duration("3650d"), Some(MAX_DURATION));
+        for too_long in ["3651d", "99999999999999w", "18446744073709551615m"] {
+            assert_eq!(parse_duration(too_long), None, "{too_long:?}");
+        }
+    }
+
+    #[test]
+    fn bans_expire_and_stay_in_their_server() {
+        let guild = GuildId::new(5);
+        let temporary = ban(BanScope::Guild(guild), Some(100));
+        assert!(temporary.is_active(99));
+        assert!(!temporary.is_active(100));
+        assert!(temporary.applies_in(Some(guild)));
+        assert!(!temporary.applies_in(Some(GuildId::new(6))));
+        assert!(!temporary.applies_in(None));
+
+        let permanent = ban(BanScope::Global, None);
+        assert!(permanent.is_active(u64::MAX));
+        assert!(permanent.applies_in(None));
+    }
+
+    #[test]
+    fn refusals_explain_the_ban() {
+        let guild = ban(BanScope::Guild(GuildId::new(5)), Some(100));
+        assert_eq!(
+            guild.refusal(),
+            "You are banned from chatting with strangers in this server until <t:100:f>."
+        );
+        let global = Ban {
+            reason: Some("spam".to_string()),
+            ..ban(BanScope::Global, None)
+        };
+        assert_eq!(
+            global.refusal(),
+            "You are banned from chatting with strangers.\nReason: spam"
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommandOption, Permissions, ResolvedValue,
+};
+use serenity::async_trait;
+use serenity::builder::CreateCommand;
+
+use super::{CommandResponse, SlashCommand};
+use crate::bans::{self, parse_duration, Ban, BanScope};
+use crate::config::get_config;
+use crate::{now_secs, GenericError};
+
+pub struct BanCommand;
+
+#[async_trait]
+impl SlashCommand for BanCommand {
+    fn name(&self) -> &'static str {
+        "ban"
+    }
+
+    fn register(&self) -> CreateCommand {
+        CreateCommand::new(self.name())
+            .description("Keep someone from chatting with strangers.")
+            .default_member_permissions(Permissions::BAN_MEMBERS)
+            .dm_permission(false)
+            .set_options(vec![
+                CreateCommandOption::new(CommandOptionType::User, "user", "Who to ban")
+                    .required(true),
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "duration",
+                    "How long, e.g. 30m, 12h, 7d or 2w. Permanent if left out",
+                )
+                .required(false),
+                CreateCommandOption::new(CommandOptionType::String, "reason", "Shown to them")
+                    .max_length(200)
+                    .required(false),
+                global_option("Ban them everywhere, not just here (bot admins only)"),
+            ])
+    }
+
+    fn defers(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Result<CommandResponse, GenericError> {
+        let scope = match requested_scope(ctx, command).await {
+            Ok(scope) => scope,
+            Err(refusal) => return Ok(CommandResponse::ephemeral(refusal)),
+        };
+        let (mut user, mut duration, mut reason) = (None, None, None);
+        for option in command.data.options() {
+            match (option.name, option.value) {
+                ("user", ResolvedValue::User(target, _)) => user = Some(target.id),
+                ("duration", ResolvedValue::String(value)) => duration = Some(value),
+                ("reason", ResolvedValue::String(value)) => reason = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        let Some(user) = user else {
+            return Ok(CommandResponse::ephemeral("Pick someone to ban"));
+        };
+
+        let now = now_secs();
+        let expires_at = match duration.map(parse_duration) {
+            Some(length) => match length.and_then(|l| now.checked_add(l.as_secs())) {
+                Some(at) => Some(at),
+                None => {
+                    return Ok(CommandResponse::ephemeral(
+                        "Could not read the duration, use something like 30m, 12h, 7d or 2w, at most 10 years",
+                    ))
+                }
+            },
+            None => None,
+        };
+        let ban = Ban {
+            user,
+            scope,
+            reason,
+            banned_by: command.user.id,
+            created_at: now,
+            expires_at,
+        };
+        bans::impose(ctx, &ban).await?;
+        Ok(CommandResponse::ephemeral(format!(
+            "Banned {}",
+            ban.describe()
+        )))
+    }
+}
+
+pub(super) fn global_option(description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::Boolean, "global", description).required(false)
+}
+
+/// This server, or everywhere if the `global` option is set. Only bot admins
+/// may act everywhere.
+pub(super) async fn requested_scope(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<BanScope, &'static str> {
+    let global = command
+        .data
+        .options()
+        .into_iter()
+        .any(|o| matches!((o.name, o.value), ("global", ResolvedValue::Boolean(true))));
+    if global {
+        return if get_config(ctx).await.bot_admins.contains(&command.user.id) {
+            Ok(BanScope::Global)
+        } else {
+            Err("Only bot admins can manage bans everywhere")
+        };
+    }
+    match command.guild_id {
+        Some(guild) => Ok(BanScope::Guild(guild)),
+        None => Err("Use this in a server"),
+    }
+}
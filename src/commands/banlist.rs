@@ -0,0 +1,76 @@
+use serenity::all::{CommandInteraction, Context, Permissions};
+use serenity::async_trait;
+use serenity::builder::CreateCommand;
+
+use super::ban::{global_option, requested_scope};
+use super::{push_list, CommandResponse, SlashCommand};
+use crate::bans::Ban;
+use crate::{get_sessions, now_secs, GenericError};
+
+pub struct BanlistCommand;
+
+#[async_trait]
+impl SlashCommand for BanlistCommand {
+    fn name(&self) -> &'static str {
+        "banlist"
+    }
+
+    fn register(&self) -> CreateCommand {
+        CreateCommand::new(self.name())
+            .description("Show who is banned from chatting with strangers.")
+            .default_member_permissions(Permissions::BAN_MEMBERS)
+            .dm_permission(false)
+            .add_option(global_option("Show bans that apply everywhere"))
+    }
+
+    async fn run(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Result<CommandResponse, GenericError> {
+        let scope = match requested_scope(ctx, command).await {
+            Ok(scope) => scope,
+            Err(refusal) => return Ok(CommandResponse::ephemeral(refusal)),
+        };
+        let sessions = get_sessions(ctx).await;
+        let bans = sessions.bans(scope, now_secs()).await?;
+        Ok(CommandResponse::ephemeral(list(&bans)))
+    }
+}
+
+fn list(bans: &[Ban]) -> String {
+    if bans.is_empty() {
+        return "No one is banned".to_string();
+    }
+    let lines: Vec<String> = bans.iter().map(Ban::describe).collect();
+    let mut out = String::new();
+    push_list(&mut out, &lines, "more");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bans::BanScope;
+    use serenity::all::UserId;
+
+    #[test]
+    fn long_lists_are_cut_short() {
+        let ban = |id| Ban {
+            user: UserId::new(id),
+            scope: BanScope::Global,
+            reason: Some("x".repeat(100)),
+            banned_by: UserId::new(1),
+            created_at: 0,
+            expires_at: None,
+        };
+        assert_eq!(list(&[]), "No one is banned");
+        let one = list(&[ban(2)]);
+        assert!(one.starts_with("<@2> (2), permanent, by <@1>: x"), "{one}");
+
+        let many: Vec<Ban> = (2..100).map(ban).collect();
+        let out = list(&many);
+        assert!(out.len() <= 2000, "{}", out.len());
+        assert!(out.ends_with(" more"), "{out}");
+    }
+}
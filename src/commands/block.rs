@@ -0,0 +1,41 @@
+use serenity::all::{CommandInteraction, Context};
+use serenity::async_trait;
+use serenity::builder::CreateCommand;
+
+use super::{CommandResponse, SlashCommand};
+use crate::components::BLOCKED;
+use crate::{block_partner, get_sessions, GenericError};
+
+pub struct Block;
+
+#[async_trait]
+impl SlashCommand for Block {
+    fn name(&self) -> &'static str {
+        "block"
+    }
+
+    fn register(&self) -> CreateCommand {
+        CreateCommand::new(self.name())
+            .description("Leave and never be matched with this stranger again.")
+    }
+
+    fn in_thread(&self) -> bool {
+        true
+    }
+
+    fn defers(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Result<CommandResponse, GenericError> {
+        let sessions = get_sessions(ctx).await;
+        let Some(channel) = block_partner(ctx, command.user.id, &*sessions).await? else {
+            return Err(GenericError::MissingSession);
+        };
+        Ok(CommandResponse::ephemeral(BLOCKED).then_close(channel))
+    }
+}
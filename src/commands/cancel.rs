@@ -1,5 +1,28 @@
-use serenity::all::CreateCommand;
+use serenity::all::{CommandInteraction, Context, CreateCommand};
+use serenity::async_trait;
 
-pub fn register() -> CreateCommand {
-    CreateCommand::new("cancel").description("Cancel current conversation.")
+use super::{CommandResponse, SlashCommand};
+use crate::{cancel_wait, get_sessions, GenericError};
+
+pub struct Cancel;
+
+#[async_trait]
+impl SlashCommand for Cancel {
+    fn name(&self) -> &'static str {
+        "cancel"
+    }
+
+    fn register(&self) -> CreateCommand {
+        CreateCommand::new(self.name()).description("Cancel current conversation.")
+    }
+
+    async fn run(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Result<CommandResponse, GenericError> {
+        let sessions = get_sessions(ctx).await;
+        let res = cancel_wait(ctx, command.user.id, &*sessions).await?;
+        Ok(CommandResponse::ephemeral(res))
+    }
 }
@@ -1,19 +1,39 @@
-use serenity::all::{CommandInteraction, Context, CreateInteractionResponseMessage};
+use serenity::all::{CommandInteraction, Context};
+use serenity::async_trait;
 use serenity::builder::CreateCommand;
 
-#[allow(unused)]
-pub async fn run(command: &CommandInteraction, ctx: &Context) -> String {
-    // println!("Interaction received: {:?}", command.data.name);
+use super::{CommandResponse, SlashCommand};
+use crate::{get_sessions, leave_chat, GenericError};
 
-    let data = CreateInteractionResponseMessage::new().content("Starting Connection");
+pub struct Leave;
 
-    // let builder = EditInteractionResponse::new().content(" You are in queue");
-    // if let Err(why) = command.edit_response(&ctx.http, builder).await {
-    //     println!("Error sending response: {:?}", why);
-    // }
-    "starting conversation".to_string()
-}
+#[async_trait]
+impl SlashCommand for Leave {
+    fn name(&self) -> &'static str {
+        "leave"
+    }
+
+    fn register(&self) -> CreateCommand {
+        CreateCommand::new(self.name()).description("Leave current conversation.")
+    }
+
+    fn in_thread(&self) -> bool {
+        true
+    }
+
+    fn defers(&self) -> bool {
+        true
+    }
 
-pub fn register() -> CreateCommand {
-    CreateCommand::new("leave").description("Leave current conversation.")
+    async fn run(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Result<CommandResponse, GenericError> {
+        let sessions = get_sessions(ctx).await;
+        let Some(channel) = leave_chat(ctx, command.user.id, &*sessions).await? else {
+            return Err(GenericError::MissingSession);
+        };
+        Ok(CommandResponse::ephemeral("You left the chat").then_close(channel))
+    }
 }
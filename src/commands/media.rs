@@ -0,0 +1,53 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommandOption, ResolvedValue,
+};
+use serenity::async_trait;
+use serenity::builder::CreateCommand;
+
+use super::{CommandResponse, SlashCommand};
+use crate::{get_sessions, GenericError};
+
+pub struct Media;
+
+#[async_trait]
+impl SlashCommand for Media {
+    fn name(&self) -> &'static str {
+        "media"
+    }
+
+    fn register(&self) -> CreateCommand {
+        CreateCommand::new(self.name())
+            .description("Choose whether strangers can send you files and stickers.")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "receive",
+                    "Receive files and stickers from strangers who turned this on too",
+                )
+                .required(true),
+            )
+    }
+
+    fn in_thread(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Result<CommandResponse, GenericError> {
+        let receive = command
+            .data
+            .options()
+            .into_iter()
+            .any(|o| matches!((o.name, o.value), ("receive", ResolvedValue::Boolean(true))));
+        let sessions = get_sessions(ctx).await;
+        sessions.set_media_opt_in(command.user.id, receive).await?;
+        Ok(CommandResponse::ephemeral(if receive {
+            "You will receive files and stickers from strangers who turned on /media too"
+        } else {
+            "Strangers can no longer send you files and stickers"
+        }))
+    }
+}
@@ -0,0 +1,135 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommandOption, Permissions, PremiumTier,
+    ResolvedValue,
+};
+use serenity::async_trait;
+use serenity::builder::CreateCommand;
+
+use super::{CommandResponse, SlashCommand};
+use crate::config::get_config;
+use crate::media::{parse_types, MediaPolicy};
+use crate::{get_sessions, GenericError};
+
+/// Largest size limit the option accepts, Discord's upload limit in fully
+/// boosted servers. What a server actually gets is capped by
+/// [`upload_limit_mb`].
+const MAX_SIZE_MB: u64 = 100;
+
+/// Discord's upload limit in MB for a server at `tier`, which bounds what the
+/// bot can re-upload there. Servers missing from the cache get the
+/// unboosted limit, which fits everywhere.
+fn upload_limit_mb(tier: Option<PremiumTier>) -> u64 {
+    match tier {
+        Some(PremiumTier::Tier2) => 50,
+        Some(PremiumTier::Tier3) => 100,
+        _ => 25,
+    }
+}
+
+pub struct MediaPolicyCommand;
+
+#[async_trait]
+impl SlashCommand for MediaPolicyCommand {
+    fn name(&self) -> &'static str {
+        "mediapolicy"
+    }
+
+    fn register(&self) -> CreateCommand {
+        CreateCommand::new(self.name())
+            .description("Set which files strangers can exchange from this server.")
+            .default_member_permissions(Permissions::MANAGE_GUILD)
+            .dm_permission(false)
+            .set_options(vec![
+                CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "enabled",
+                    "Relay files and stickers at all",
+                )
+                .required(false),
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "max_size_mb",
+                    "Largest file relayed, in MB, up to this server's upload limit",
+                )
+                .min_int_value(1)
+                .max_int_value(MAX_SIZE_MB)
+                .required(false),
+                CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "image_only",
+                    "Only relay images",
+                )
+                .required(false),
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "types",
+                    "Allowed MIME types or extensions seperated by comma, e.g. image/*,pdf. \"any\" allows all",
+                )
+                .required(false),
+            ])
+    }
+
+    async fn run(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Result<CommandResponse, GenericError> {
+        let Some(guild) = command.guild_id else {
+            return Ok(CommandResponse::ephemeral("Use this in a server"));
+        };
+        let sessions = get_sessions(ctx).await;
+        let current = match sessions.media_policy(guild).await? {
+            Some(policy) => policy,
+            None => get_config(ctx).await.media_policy.clone(),
+        };
+        let tier = ctx.cache.guild(guild).map(|g| g.premium_tier);
+        let policy = apply_options(current, command, upload_limit_mb(tier));
+        sessions.set_media_policy(guild, &policy).await?;
+        Ok(CommandResponse::ephemeral(format!(
+            "Media policy for this server:\n{}",
+            policy.describe()
+        )))
+    }
+}
+
+/// `policy` with the options the admin passed; the others keep their value.
+/// The size limit stays within the server's `upload_limit` in MB.
+fn apply_options(
+    mut policy: MediaPolicy,
+    command: &CommandInteraction,
+    upload_limit: u64,
+) -> MediaPolicy {
+    for option in command.data.options() {
+        match (option.name, option.value) {
+            ("enabled", ResolvedValue::Boolean(enabled)) => policy.enabled = enabled,
+            ("max_size_mb", ResolvedValue::Integer(mb)) => {
+                let mb = mb.clamp(1, upload_limit as i64) as u32;
+                policy.max_bytes = mb * 1024 * 1024;
+            }
+            ("image_only", ResolvedValue::Boolean(image_only)) => policy.image_only = image_only,
+            ("types", ResolvedValue::String(types)) => {
+                policy.allowed_types = if types.trim().eq_ignore_ascii_case("any") {
+                    vec![]
+                } else {
+                    parse_types(types)
+                };
+            }
+            _ => {}
+        }
+    }
+    policy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_limit_follows_boosts() {
+        assert_eq!(upload_limit_mb(None), 25);
+        assert_eq!(upload_limit_mb(Some(PremiumTier::Tier0)), 25);
+        assert_eq!(upload_limit_mb(Some(PremiumTier::Tier1)), 25);
+        assert_eq!(upload_limit_mb(Some(PremiumTier::Tier2)), 50);
+        assert_eq!(upload_limit_mb(Some(PremiumTier::Tier3)), MAX_SIZE_MB);
+    }
+}
@@ -1,4 +1,250 @@
+//! Slash commands. Each command is a unit struct implementing
+//! [`SlashCommand`] and listed in [`COMMANDS`], which drives both registration
+//! in `ready` and dispatch in `interaction_create`. Debug commands in
+//! [`UNREGISTERED`] are dispatched but never registered.
+
+pub mod ban;
+pub mod banlist;
+pub mod block;
 pub mod cancel;
 pub mod leave;
+pub mod media;
+pub mod media_policy;
+pub mod next;
 pub mod ping;
+pub mod report;
 pub mod start;
+pub mod strikes;
+pub mod unban;
+
+use serenity::all::{
+    ChannelId, ChannelType, Command, CommandInteraction, Context, CreateActionRow, CreateButton,
+    CreateCommand, CreateInteractionResponse, CreateInteractionResponseMessage,
+    EditInteractionResponse,
+};
+use serenity::async_trait;
+
+use crate::components::Menu;
+use crate::error::{report_error, ErrorContext};
+use crate::{close_thread, GenericError};
+
+/// Leaves room under Discord's 2000 character message limit.
+const MAX_LIST_CHARS: usize = 1800;
+
+/// Adds `lines` to `out`, one per line, for as long as the reply fits in a
+/// message, then how many were left out, e.g. "…and 3 more".
+fn push_list(out: &mut String, lines: &[String], left_out: &str) {
+    for (shown, line) in lines.iter().enumerate() {
+        if out.len() + line.len() + 1 > MAX_LIST_CHARS {
+            out.push_str(&format!("…and {} {left_out}", lines.len() - shown));
+            return;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+}
+
+/// What a command answers its interaction with.
+pub struct CommandResponse {
+    content: String,
+    ephemeral: bool,
+    buttons: Vec<CreateButton>,
+    menu: Option<Menu>,
+    /// Locked and archived once the reply is sent, for commands that close
+    /// the thread they were used in.
+    close_thread: Option<ChannelId>,
+}
+
+impl CommandResponse {
+    /// A reply everyone in the channel can see.
+    pub fn reply(content: impl Into<String>) -> CommandResponse {
+        CommandResponse {
+            content: content.into(),
+            ephemeral: false,
+            buttons: vec![],
+            menu: None,
+            close_thread: None,
+        }
+    }
+
+    /// A reply only the caller can see.
+    pub fn ephemeral(content: impl Into<String>) -> CommandResponse {
+        CommandResponse {
+            ephemeral: true,
+            ..CommandResponse::reply(content)
+        }
+    }
+
+    pub fn button(mut self, button: CreateButton) -> CommandResponse {
+        self.buttons.push(button);
+        self
+    }
+
+    pub fn menu(mut self, menu: Menu) -> CommandResponse {
+        self.menu = Some(menu);
+        self
+    }
+
+    pub fn then_close(mut self, thread: ChannelId) -> CommandResponse {
+        self.close_thread = Some(thread);
+        self
+    }
+
+    fn components(&self) -> Vec<CreateActionRow> {
+        let mut rows = vec![];
+        if !self.buttons.is_empty() {
+            rows.push(CreateActionRow::Buttons(self.buttons.clone()));
+        }
+        if let Some(menu) = self.menu {
+            rows.push(CreateActionRow::SelectMenu(menu.build()));
+        }
+        rows
+    }
+}
+
+#[async_trait]
+pub trait SlashCommand: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn register(&self) -> CreateCommand;
+
+    /// Whether the command may be used inside a private chat thread.
+    fn in_thread(&self) -> bool {
+        false
+    }
+
+    /// Commands that can take longer than Discord's three second deadline
+    /// defer first, showing the caller an ephemeral "thinking" state.
+    fn defers(&self) -> bool {
+        false
+    }
+
+    async fn run(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Result<CommandResponse, GenericError>;
+}
+
+pub static COMMANDS: &[&dyn SlashCommand] = &[
+    &start::Start,
+    &leave::Leave,
+    &next::Next,
+    &cancel::Cancel,
+    &block::Block,
+    &media::Media,
+    &media_policy::MediaPolicyCommand,
+    &report::ReportCommand,
+    &ban::BanCommand,
+    &unban::UnbanCommand,
+    &banlist::BanlistCommand,
+    &strikes::StrikesCommand,
+];
+
+/// Debug commands, answered if invoked but left out of the registered list.
+pub static UNREGISTERED: &[&dyn SlashCommand] = &[&ping::Ping];
+
+fn find(name: &str) -> Option<&'static dyn SlashCommand> {
+    COMMANDS
+        .iter()
+        .chain(UNREGISTERED)
+        .copied()
+        .find(|c| c.name() == name)
+}
+
+/// Replaces the global command list with everything in [`COMMANDS`].
+pub async fn register_all(ctx: &Context) -> Result<Vec<Command>, GenericError> {
+    let commands = COMMANDS.iter().map(|c| c.register()).collect();
+    Ok(Command::set_global_commands(&ctx.http, commands).await?)
+}
+
+/// Runs the command behind `command` and answers the interaction with its
+/// response, or with a generic error message if it failed.
+pub async fn dispatch(ctx: &Context, command: &CommandInteraction) -> Result<(), GenericError> {
+    let Some(handler) = find(&command.data.name) else {
+        let response = CommandResponse::ephemeral("Unknown command");
+        return respond(ctx, command, response, false).await;
+    };
+
+    let in_thread = command
+        .channel
+        .as_ref()
+        .is_some_and(|c| c.kind == ChannelType::PrivateThread);
+    if in_thread && !handler.in_thread() {
+        let allowed: Vec<String> = COMMANDS
+            .iter()
+            .filter(|c| c.in_thread())
+            .map(|c| format!("/{}", c.name()))
+            .collect();
+        let response = CommandResponse::ephemeral(format!(
+            "You can only use {} in the thread",
+            allowed.join(", ")
+        ));
+        return respond(ctx, command, response, false).await;
+    }
+
+    let deferred = handler.defers();
+    if deferred {
+        command.defer_ephemeral(&ctx.http).await?;
+    }
+    let response = match handler.run(ctx, command).await {
+        Ok(response) => response,
+        Err(e) => {
+            report_error(ctx, &ErrorContext::command(command), &e).await;
+            CommandResponse::ephemeral(e.user_message())
+        }
+    };
+    let close = response.close_thread;
+    respond(ctx, command, response, deferred).await?;
+    if let Some(thread) = close {
+        close_thread(ctx, thread).await?;
+    }
+    Ok(())
+}
+
+async fn respond(
+    ctx: &Context,
+    command: &CommandInteraction,
+    response: CommandResponse,
+    deferred: bool,
+) -> Result<(), GenericError> {
+    let components = response.components();
+    if deferred {
+        command
+            .edit_response(
+                &ctx.http,
+                EditInteractionResponse::new()
+                    .content(response.content)
+                    .components(components),
+            )
+            .await?;
+    } else {
+        command
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content(response.content)
+                        .ephemeral(response.ephemeral)
+                        .components(components),
+                ),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_command_is_found_by_its_own_name() {
+        for command in COMMANDS.iter().chain(UNREGISTERED) {
+            let found = find(command.name()).expect("known command");
+            assert!(std::ptr::addr_eq(found, *command));
+        }
+        assert!(find("nope").is_none());
+        assert!(COMMANDS.iter().all(|c| c.name() != "pinga"));
+    }
+}
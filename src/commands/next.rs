@@ -0,0 +1,38 @@
+use serenity::all::{CommandInteraction, Context};
+use serenity::async_trait;
+use serenity::builder::CreateCommand;
+
+use super::{CommandResponse, SlashCommand};
+use crate::{get_sessions, skip_partner, GenericError};
+
+pub struct Next;
+
+#[async_trait]
+impl SlashCommand for Next {
+    fn name(&self) -> &'static str {
+        "next"
+    }
+
+    fn register(&self) -> CreateCommand {
+        CreateCommand::new(self.name())
+            .description("Skip to a new stranger, keeping this thread and your interests.")
+    }
+
+    fn in_thread(&self) -> bool {
+        true
+    }
+
+    fn defers(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Result<CommandResponse, GenericError> {
+        let sessions = get_sessions(ctx).await;
+        let msg = skip_partner(ctx, command.user.id, &*sessions).await?;
+        Ok(CommandResponse::ephemeral(msg))
+    }
+}
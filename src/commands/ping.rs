@@ -1,11 +1,27 @@
+use serenity::all::{CommandInteraction, Context};
+use serenity::async_trait;
 use serenity::builder::CreateCommand;
-use serenity::model::application::ResolvedOption;
 
-pub fn run(_options: &[ResolvedOption]) -> String {
-    "Hey, I'm alive!".to_string()
-}
+use super::{CommandResponse, SlashCommand};
+use crate::GenericError;
+
+pub struct Ping;
+
+#[async_trait]
+impl SlashCommand for Ping {
+    fn name(&self) -> &'static str {
+        "pinga"
+    }
+
+    fn register(&self) -> CreateCommand {
+        CreateCommand::new(self.name()).description("A pinga command")
+    }
 
-#[allow(unused)]
-pub fn register() -> CreateCommand {
-    CreateCommand::new("pinga").description("A pinga command")
+    async fn run(
+        &self,
+        _ctx: &Context,
+        _command: &CommandInteraction,
+    ) -> Result<CommandResponse, GenericError> {
+        Ok(CommandResponse::reply("Hey, I'm alive!"))
+    }
 }
@@ -0,0 +1,51 @@
+use serenity::all::{CommandInteraction, Context};
+use serenity::async_trait;
+use serenity::builder::CreateCommand;
+
+use super::{CommandResponse, SlashCommand};
+use crate::components::Menu;
+use crate::config::get_config;
+use crate::{get_sessions, GenericError};
+
+pub struct ReportCommand;
+
+#[async_trait]
+impl SlashCommand for ReportCommand {
+    fn name(&self) -> &'static str {
+        "report"
+    }
+
+    fn register(&self) -> CreateCommand {
+        CreateCommand::new(self.name()).description("Report the stranger you are talking to.")
+    }
+
+    fn in_thread(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Result<CommandResponse, GenericError> {
+        if get_config(ctx).await.mod_channel.is_none() {
+            return Ok(CommandResponse::ephemeral(
+                "Reporting is not set up on this bot yet",
+            ));
+        }
+        let sessions = get_sessions(ctx).await;
+        let owner = sessions
+            .user_by_channel(command.channel_id)
+            .await?
+            .filter(|u| u.id == command.user.id);
+        let Some(owner) = owner else {
+            return Err(GenericError::MissingSession);
+        };
+        let Some(stranger) = owner.partner else {
+            return Ok(CommandResponse::ephemeral(
+                "There is no stranger here to report. If they already left, use the Report button on the message saying so",
+            ));
+        };
+        Ok(CommandResponse::ephemeral("What happened?").menu(Menu::ReportReason(stranger)))
+    }
+}
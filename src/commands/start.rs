@@ -1,41 +1,65 @@
 use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommandOption};
+use serenity::async_trait;
 use serenity::builder::CreateCommand;
 
-#[allow(unused)]
-pub async fn run(command: &CommandInteraction, ctx: &Context) -> String {
-    // println!("Interaction received: {:?}", command.data.name);
-    println!("inside Start command");
+use super::{CommandResponse, SlashCommand};
+use crate::components::Button;
+use crate::{get_sessions, matcher, start_options, GenericError};
 
-    // let data = CreateInteractionResponseMessage::new().content("Starting Connection");
+pub struct Start;
 
-    // let builder = EditInteractionResponse::new().content(" You are in queue");
-    // if let Err(why) = command.edit_response(&ctx.http, builder).await {
-    //     println!("Error sending response: {:?}", why);
-    // }
-    "starting conversation".to_string()
-}
+#[async_trait]
+impl SlashCommand for Start {
+    fn name(&self) -> &'static str {
+        "start"
+    }
+
+    fn register(&self) -> CreateCommand {
+        CreateCommand::new(self.name())
+            .description("Start convesation with random person.")
+            .set_options(vec![
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "interest",
+                    "Interest of the person seperated by comma",
+                )
+                .required(false),
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "wait",
+                    "Time to wait before atleast 1 interest match in seconds\n Default is 10 seconds",
+                )
+                .required(false),
+                CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "anyone",
+                    "If true, match with anyone irrespective of interest",
+                )
+                .required(false),
+            ])
+    }
+
+    fn defers(&self) -> bool {
+        true
+    }
 
-pub fn register() -> CreateCommand {
-    CreateCommand::new("start")
-        .description("Start convesation with random person.")
-        .set_options(vec![
-            CreateCommandOption::new(
-                CommandOptionType::String,
-                "interest",
-                "Interest of the person seperated by comma",
-            )
-            .required(false),
-            CreateCommandOption::new(
-                CommandOptionType::Integer,
-                "wait",
-                "Time to wait before atleast 1 interest match in seconds\n Default is 10 seconds",
-            )
-            .required(false),
-            CreateCommandOption::new(
-                CommandOptionType::Boolean,
-                "anyone",
-                "If true, match with anyone irrespective of interest",
-            )
-            .required(false),
-        ])
+    async fn run(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Result<CommandResponse, GenericError> {
+        println!("inside Start command");
+        let sessions = get_sessions(ctx).await;
+        let options = start_options(command);
+        let admission = matcher(
+            ctx,
+            command.user.id,
+            command.guild_id,
+            command.channel_id,
+            options,
+            &*sessions,
+        )
+        .await?;
+        Ok(CommandResponse::ephemeral(admission.message()).button(Button::Cancel.build()))
+    }
 }
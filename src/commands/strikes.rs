@@ -0,0 +1,154 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommandOption, Permissions, ResolvedValue,
+};
+use serenity::async_trait;
+use serenity::builder::CreateCommand;
+
+use super::{push_list, CommandResponse, SlashCommand};
+use crate::config::get_config;
+use crate::strikes::{Strike, StrikePolicy};
+use crate::{get_sessions, now_secs, GenericError};
+
+pub struct StrikesCommand;
+
+#[async_trait]
+impl SlashCommand for StrikesCommand {
+    fn name(&self) -> &'static str {
+        "strikes"
+    }
+
+    fn register(&self) -> CreateCommand {
+        CreateCommand::new(self.name())
+            .description("Show or clear someone's strikes.")
+            .default_member_permissions(Permissions::BAN_MEMBERS)
+            .dm_permission(false)
+            .set_options(vec![
+                CreateCommandOption::new(CommandOptionType::User, "user", "Whose strikes")
+                    .required(true),
+                CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "clear",
+                    "Wipe their strikes (bot admins and moderators of the report server)",
+                )
+                .required(false),
+            ])
+    }
+
+    async fn run(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Result<CommandResponse, GenericError> {
+        let (mut user, mut clear) = (None, false);
+        for option in command.data.options() {
+            match (option.name, option.value) {
+                ("user", ResolvedValue::User(target, _)) => user = Some(target.id),
+                ("clear", ResolvedValue::Boolean(flag)) => clear = flag,
+                _ => {}
+            }
+        }
+        let Some(user) = user else {
+            return Ok(CommandResponse::ephemeral("Pick someone"));
+        };
+        let config = get_config(ctx).await;
+        let sessions = get_sessions(ctx).await;
+
+        if clear {
+            // Strikes count everywhere, so only those who review reports
+            // may wipe them.
+            if !may_clear(ctx, command).await {
+                return Ok(CommandResponse::ephemeral(
+                    "Only bot admins and moderators of the report server can clear strikes",
+                ));
+            }
+            let cleared = sessions.clear_strikes(user).await?;
+            return Ok(CommandResponse::ephemeral(format!(
+                "Cleared {cleared} strikes of <@{user}>"
+            )));
+        }
+        let strikes = sessions.strikes(user).await?;
+        Ok(CommandResponse::ephemeral(summary(
+            &strikes,
+            &config.strike_policy,
+            now_secs(),
+        )))
+    }
+}
+
+/// Whether the invoker may wipe strikes: a bot admin, or someone who can ban
+/// members in the server the mod channel is in.
+async fn may_clear(ctx: &Context, command: &CommandInteraction) -> bool {
+    let config = get_config(ctx).await;
+    if config.bot_admins.contains(&command.user.id) {
+        return true;
+    }
+    let can_ban = command
+        .member
+        .as_ref()
+        .and_then(|m| m.permissions)
+        .is_some_and(|p| p.ban_members());
+    let Some(mod_channel) = config.mod_channel else {
+        return false;
+    };
+    let mod_guild = match mod_channel.to_channel(ctx).await {
+        Ok(channel) => channel.guild().map(|c| c.guild_id),
+        Err(_) => None,
+    };
+    can_ban && mod_guild.is_some() && mod_guild == command.guild_id
+}
+
+fn summary(strikes: &[Strike], policy: &StrikePolicy, now: u64) -> String {
+    let Some(first) = strikes.first() else {
+        return "No strikes".to_string();
+    };
+    let mut out = format!("<@{}> has {} strikes", first.user, strikes.len());
+    if let Some(restriction) = policy.restriction(strikes, now) {
+        out.push_str(&format!(" and is {}", restriction.describe()));
+    }
+    out.push('\n');
+    // Newest first, as the latest strikes matter most.
+    let lines: Vec<String> = strikes
+        .iter()
+        .rev()
+        .map(|strike| {
+            let by = match strike.issued_by {
+                Some(moderator) => format!("<@{moderator}>"),
+                None => "filter".to_string(),
+            };
+            format!("<t:{}:d> by {by}: {}", strike.at, strike.reason)
+        })
+        .collect();
+    push_list(&mut out, &lines, "older");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serenity::all::UserId;
+
+    #[test]
+    fn summary_lists_newest_first() {
+        let strike = |at, issued_by: Option<u64>| Strike {
+            user: UserId::new(1),
+            reason: format!("strike at {at}"),
+            issued_by: issued_by.map(UserId::new),
+            at,
+        };
+        let policy = StrikePolicy::default();
+        assert_eq!(summary(&[], &policy, 0), "No strikes");
+        assert_eq!(
+            summary(&[strike(10, None), strike(20, Some(9))], &policy, 30),
+            "<@1> has 2 strikes\n<t:20:d> by <@9>: strike at 20\n<t:10:d> by filter: strike at 10\n"
+        );
+        let three = [strike(10, None), strike(20, None), strike(30, None)];
+        let out = summary(&three, &policy, 40);
+        assert!(
+            out.starts_with(&format!(
+                "<@1> has 3 strikes and is on cooldown until <t:{}:f>\n",
+                30 + policy.cooldown.as_secs()
+            )),
+            "{out}"
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommandOption, Permissions, ResolvedValue,
+};
+use serenity::async_trait;
+use serenity::builder::CreateCommand;
+
+use super::ban::{global_option, requested_scope};
+use super::{CommandResponse, SlashCommand};
+use crate::{get_sessions, GenericError};
+
+pub struct UnbanCommand;
+
+#[async_trait]
+impl SlashCommand for UnbanCommand {
+    fn name(&self) -> &'static str {
+        "unban"
+    }
+
+    fn register(&self) -> CreateCommand {
+        CreateCommand::new(self.name())
+            .description("Let someone chat with strangers again.")
+            .default_member_permissions(Permissions::BAN_MEMBERS)
+            .dm_permission(false)
+            .set_options(vec![
+                CreateCommandOption::new(CommandOptionType::User, "user", "Who to unban")
+                    .required(true),
+                global_option("Lift their ban everywhere (bot admins only)"),
+            ])
+    }
+
+    async fn run(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Result<CommandResponse, GenericError> {
+        let scope = match requested_scope(ctx, command).await {
+            Ok(scope) => scope,
+            Err(refusal) => return Ok(CommandResponse::ephemeral(refusal)),
+        };
+        let user = command
+            .data
+            .options()
+            .into_iter()
+            .find_map(|o| match (o.name, o.value) {
+                ("user", ResolvedValue::User(user, _)) => Some(user.id),
+                _ => None,
+            });
+        let Some(user) = user else {
+            return Ok(CommandResponse::ephemeral("Pick someone to unban"));
+        };
+        let sessions = get_sessions(ctx).await;
+        Ok(CommandResponse::ephemeral(
+            if sessions.remove_ban(user, scope).await? {
+                format!("<@{user}> can chat with strangers again")
+            } else {
+                format!("<@{user}> was not banned")
+            },
+        ))
+    }
+}
@@ -0,0 +1,499 @@
+//! Button presses and select menu choices. Every component carries a
+//! structured `custom_id` (see [`Button`] and [`Menu`]) and is handled here
+//! from whatever state the session store holds, so components keep working
+//! after a restart or from another process.
+//!
+//! Every press is acknowledged straight away and answered either by replacing
+//! the message the button was on or with an ephemeral reply to whoever
+//! pressed it.
+
+use std::num::NonZeroU64;
+use std::time::Duration;
+
+use serenity::all::{
+    ButtonStyle, ChannelId, ComponentInteraction, ComponentInteractionDataKind, Context,
+    CreateActionRow, CreateAllowedMentions, CreateButton, CreateInteractionResponseFollowup,
+    CreateMessage, CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption,
+    EditInteractionResponse, UserId,
+};
+
+use crate::config::get_config;
+use crate::error::{report_error, ErrorContext};
+use crate::reports::{self, Outcome, Reason};
+use crate::store::User;
+use crate::{
+    block_partner, cancel_wait, close_thread, get_sessions, leave_chat, matcher, skip_partner,
+    Admission, GenericError,
+};
+
+/// Every button the bot sends. The `custom_id` is the kind, followed by the
+/// user it is about where the handler cannot find that in the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    /// Stop waiting for a partner (or end the chat, if already paired).
+    Cancel,
+    /// Skip the current stranger, or start a new search from a closed chat.
+    Next,
+    /// End the current chat.
+    Leave,
+    /// Report a stranger, who may already have left.
+    Report(UserId),
+    /// Block a stranger, ending the chat if it is still going.
+    Block(UserId),
+    /// Show the partner who you are.
+    Reveal,
+    /// Close a thread whose chat is over, for the user it belongs to.
+    Close(UserId),
+    /// A moderator's decision on a report.
+    Review(u64, Outcome),
+}
+
+impl Button {
+    pub fn custom_id(self) -> String {
+        match self {
+            Button::Cancel => "cancel".to_string(),
+            Button::Next => "next".to_string(),
+            Button::Leave => "leave".to_string(),
+            Button::Report(user) => format!("report:{user}"),
+            Button::Block(user) => format!("block:{user}"),
+            Button::Reveal => "reveal".to_string(),
+            Button::Close(owner) => format!("close:{owner}"),
+            Button::Review(report, outcome) => format!("review:{report}:{}", outcome.as_str()),
+        }
+    }
+
+    pub fn parse(custom_id: &str) -> Option<Button> {
+        let (kind, arg) = match custom_id.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg)),
+            None => (custom_id, None),
+        };
+        let user = || arg?.parse::<NonZeroU64>().ok().map(UserId::from);
+        match (kind, arg) {
+            ("cancel", None) => Some(Button::Cancel),
+            ("next", None) => Some(Button::Next),
+            ("leave", None) => Some(Button::Leave),
+            ("report", Some(_)) => user().map(Button::Report),
+            ("block", Some(_)) => user().map(Button::Block),
+            ("reveal", None) => Some(Button::Reveal),
+            ("close", Some(_)) => user().map(Button::Close),
+            ("review", Some(arg)) => {
+                let (report, outcome) = arg.split_once(':')?;
+                Some(Button::Review(
+                    report.parse().ok()?,
+                    Outcome::parse(outcome)?,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn build(self) -> CreateButton {
+        let (style, label) = match self {
+            Button::Cancel => (ButtonStyle::Danger, "Cancel"),
+            Button::Next => (ButtonStyle::Primary, "Next stranger"),
+            Button::Leave => (ButtonStyle::Danger, "Leave"),
+            Button::Report(_) => (ButtonStyle::Danger, "Report"),
+            Button::Block(_) => (ButtonStyle::Danger, "Block"),
+            Button::Reveal => (ButtonStyle::Secondary, "Reveal myself"),
+            Button::Close(_) => (ButtonStyle::Secondary, "Close"),
+            Button::Review(_, Outcome::Ban) => (ButtonStyle::Danger, "Ban"),
+            Button::Review(_, Outcome::Warn) => (ButtonStyle::Primary, "Warn"),
+            Button::Review(_, Outcome::Dismiss) => (ButtonStyle::Secondary, "Dismiss"),
+        };
+        CreateButton::new(self.custom_id())
+            .style(style)
+            .label(label)
+    }
+}
+
+/// Every select menu the bot sends, with a `custom_id` like [`Button`]'s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Menu {
+    /// Pick why a stranger is being reported.
+    ReportReason(UserId),
+}
+
+impl Menu {
+    pub fn custom_id(self) -> String {
+        match self {
+            Menu::ReportReason(user) => format!("report_reason:{user}"),
+        }
+    }
+
+    pub fn parse(custom_id: &str) -> Option<Menu> {
+        let (kind, arg) = custom_id.split_once(':')?;
+        match kind {
+            "report_reason" => arg
+                .parse::<NonZeroU64>()
+                .ok()
+                .map(|user| Menu::ReportReason(UserId::from(user))),
+            _ => None,
+        }
+    }
+
+    pub fn build(self) -> CreateSelectMenu {
+        match self {
+            Menu::ReportReason(_) => {
+                let options = Reason::ALL
+                    .into_iter()
+                    .map(|r| CreateSelectMenuOption::new(r.label(), r.as_str()))
+                    .collect();
+                CreateSelectMenu::new(self.custom_id(), CreateSelectMenuKind::String { options })
+                    .placeholder("Why are you reporting this stranger?")
+            }
+        }
+    }
+}
+
+/// What someone who blocked their partner is told.
+pub const BLOCKED: &str = "Stranger blocked. You will not be matched with them again";
+
+pub fn describe(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 60 && secs.is_multiple_of(60) {
+        format!("{} minutes", secs / 60)
+    } else {
+        format!("{secs} seconds")
+    }
+}
+
+/// The "connected" notice posted to each side of a new pair, with the
+/// controls for the chat.
+pub fn connected_message(content: &str, partner: &User) -> CreateMessage {
+    CreateMessage::new()
+        .content(content)
+        .button(Button::Next.build())
+        .button(Button::Leave.build())
+        .button(Button::Reveal.build())
+        .button(Button::Report(partner.id).build())
+        .button(Button::Block(partner.id).build())
+}
+
+/// The notice posted to `owner` when their partner (`stranger`) left.
+pub fn partner_left_message(owner: UserId, stranger: UserId, grace: Duration) -> CreateMessage {
+    CreateMessage::new()
+        .content(format!(
+            "Stranger has left the chat. This thread will be closed in {}.",
+            describe(grace)
+        ))
+        .button(Button::Next.build())
+        .button(Button::Report(stranger).build())
+        .button(Button::Block(stranger).build())
+        .button(Button::Close(owner).build())
+}
+
+struct ButtonReply {
+    content: String,
+    /// Whether the reply replaces the message the button was on (dropping its
+    /// buttons) rather than going only to the presser.
+    update: bool,
+    /// Locked and archived once the reply is sent.
+    close_thread: Option<ChannelId>,
+    menu: Option<Menu>,
+}
+
+impl ButtonReply {
+    fn update(content: impl Into<String>) -> ButtonReply {
+        ButtonReply {
+            content: content.into(),
+            update: true,
+            close_thread: None,
+            menu: None,
+        }
+    }
+
+    fn ephemeral(content: impl Into<String>) -> ButtonReply {
+        ButtonReply {
+            update: false,
+            ..ButtonReply::update(content)
+        }
+    }
+
+    fn then_close(mut self, thread: ChannelId) -> ButtonReply {
+        self.close_thread = Some(thread);
+        self
+    }
+
+    fn menu(mut self, menu: Menu) -> ButtonReply {
+        self.menu = Some(menu);
+        self
+    }
+
+    fn components(&self) -> Vec<CreateActionRow> {
+        match self.menu {
+            Some(menu) => vec![CreateActionRow::SelectMenu(menu.build())],
+            None => vec![],
+        }
+    }
+}
+
+pub async fn dispatch(ctx: &Context, component: &ComponentInteraction) -> Result<(), GenericError> {
+    component.defer(&ctx.http).await?;
+
+    let custom_id = &component.data.custom_id;
+    let reply = if let Some(button) = Button::parse(custom_id) {
+        press(ctx, component, button).await
+    } else if let Some(menu) = Menu::parse(custom_id) {
+        choose(ctx, component, menu).await
+    } else {
+        Ok(ButtonReply::ephemeral("This button is no longer active"))
+    };
+    let reply = match reply {
+        Ok(reply) => reply,
+        Err(e) => {
+            report_error(ctx, &ErrorContext::component(component), &e).await;
+            ButtonReply::ephemeral(e.user_message())
+        }
+    };
+
+    if reply.update {
+        component
+            .edit_response(
+                &ctx.http,
+                EditInteractionResponse::new()
+                    .components(reply.components())
+                    .content(reply.content),
+            )
+            .await?;
+    } else {
+        component
+            .create_followup(
+                &ctx.http,
+                CreateInteractionResponseFollowup::new()
+                    .components(reply.components())
+                    .content(reply.content)
+                    .ephemeral(true),
+            )
+            .await?;
+    }
+    if let Some(thread) = reply.close_thread {
+        close_thread(ctx, thread).await?;
+    }
+    Ok(())
+}
+
+async fn press(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    button: Button,
+) -> Result<ButtonReply, GenericError> {
+    let sessions = get_sessions(ctx).await;
+    let presser = component.user.id;
+    match button {
+        Button::Cancel => {
+            let res = cancel_wait(ctx, presser, &*sessions).await?;
+            Ok(ButtonReply::update(res))
+        }
+        Button::Report(stranger) => Ok(report(ctx, stranger).await),
+        Button::Review(report, outcome) => {
+            if !may_review(ctx, component).await {
+                return Ok(ButtonReply::ephemeral("Only moderators can decide reports"));
+            }
+            match reports::review(ctx, report, outcome, presser).await? {
+                Some(result) => Ok(ButtonReply::update(result)),
+                None => Ok(ButtonReply::ephemeral("This report was already handled")),
+            }
+        }
+        Button::Close(owner) if owner != presser => Ok(ButtonReply::ephemeral(
+            "Only the owner of this chat can use that",
+        )),
+        Button::Close(_) => Ok(ButtonReply::update("Chat closed").then_close(component.channel_id)),
+        Button::Block(stranger) => block(ctx, component, stranger).await,
+        Button::Next | Button::Leave | Button::Reveal => {
+            let owner = sessions.user_by_channel(component.channel_id).await?;
+            match (button, owner) {
+                (_, Some(owner)) if owner.id != presser => Ok(ButtonReply::ephemeral(
+                    "Only the owner of this chat can use that",
+                )),
+                (Button::Next, Some(_)) => {
+                    let msg = skip_partner(ctx, presser, &*sessions).await?;
+                    Ok(ButtonReply::ephemeral(msg))
+                }
+                (Button::Next, None) => next_stranger(ctx, component).await,
+                (Button::Leave, Some(_)) => match leave_chat(ctx, presser, &*sessions).await? {
+                    Some(own) => Ok(ButtonReply::update("You left the chat").then_close(own)),
+                    None => Err(GenericError::MissingSession),
+                },
+                (Button::Reveal, Some(owner)) => reveal(ctx, &owner).await,
+                _ => Err(GenericError::MissingSession),
+            }
+        }
+    }
+}
+
+/// Starts a fresh search from a thread whose chat ended, with the options of
+/// the user's last search. The new thread goes where the old one was, and the
+/// old one is closed once the user is back in the queue.
+async fn next_stranger(
+    ctx: &Context,
+    component: &ComponentInteraction,
+) -> Result<ButtonReply, GenericError> {
+    let thread = component.channel_id;
+    let parent = thread
+        .to_channel(&ctx.http)
+        .await?
+        .guild()
+        .and_then(|c| c.parent_id);
+    let Some(parent) = parent else {
+        return Ok(ButtonReply::ephemeral("Use /start to meet someone new"));
+    };
+    let sessions = get_sessions(ctx).await;
+    let user = component.user.id;
+    let options = sessions.last_search(user).await?.unwrap_or_default();
+    match matcher(ctx, user, component.guild_id, parent, options, &*sessions).await? {
+        Admission::Queued(msg) => Ok(ButtonReply::update(msg).then_close(thread)),
+        Admission::Refused(msg) => Ok(ButtonReply::ephemeral(msg)),
+    }
+}
+
+/// Blocks `stranger` for whoever pressed. If the two are still chatting the
+/// chat ends too; otherwise the stranger already left, or the button is from
+/// an earlier chat in the same thread.
+async fn block(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    stranger: UserId,
+) -> Result<ButtonReply, GenericError> {
+    let sessions = get_sessions(ctx).await;
+    let presser = component.user.id;
+    match sessions.user_by_channel(component.channel_id).await? {
+        Some(owner) if owner.id != presser => Ok(ButtonReply::ephemeral(
+            "Only the owner of this chat can use that",
+        )),
+        Some(owner) if owner.partner == Some(stranger) => {
+            match block_partner(ctx, presser, &*sessions).await? {
+                Some(own) => Ok(ButtonReply::update(BLOCKED).then_close(own)),
+                None => Err(GenericError::MissingSession),
+            }
+        }
+        _ => {
+            sessions.block(presser, stranger).await?;
+            Ok(ButtonReply::ephemeral(BLOCKED))
+        }
+    }
+}
+
+async fn reveal(ctx: &Context, owner: &User) -> Result<ButtonReply, GenericError> {
+    let Some(partner_channel) = owner.partner_channel else {
+        return Err(GenericError::MissingSession);
+    };
+    partner_channel
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(format!("Stranger revealed themselves: <@{}>", owner.id))
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    Ok(ButtonReply::ephemeral(
+        "Your partner can now see who you are",
+    ))
+}
+
+/// Whether the presser may decide reports: a bot admin, or someone who can
+/// ban members where the moderator channel is.
+async fn may_review(ctx: &Context, component: &ComponentInteraction) -> bool {
+    let config = get_config(ctx).await;
+    if config.bot_admins.contains(&component.user.id) {
+        return true;
+    }
+    let can_ban = component
+        .member
+        .as_ref()
+        .and_then(|m| m.permissions)
+        .is_some_and(|p| p.ban_members());
+    can_ban && config.mod_channel == Some(component.channel_id)
+}
+
+/// Asks why `stranger` is being reported; the report is filed once a
+/// reason is picked.
+async fn report(ctx: &Context, stranger: UserId) -> ButtonReply {
+    if get_config(ctx).await.mod_channel.is_none() {
+        return ButtonReply::ephemeral("Reporting is not set up on this bot yet");
+    }
+    ButtonReply::ephemeral("What happened?").menu(Menu::ReportReason(stranger))
+}
+
+async fn choose(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    menu: Menu,
+) -> Result<ButtonReply, GenericError> {
+    let ComponentInteractionDataKind::StringSelect { values } = &component.data.kind else {
+        return Ok(ButtonReply::ephemeral("This menu is no longer active"));
+    };
+    match menu {
+        Menu::ReportReason(stranger) => {
+            let Some(reason) = values.first().and_then(|v| Reason::parse(v)) else {
+                return Ok(ButtonReply::ephemeral("This menu is no longer active"));
+            };
+            let Some(guild) = component.guild_id else {
+                return Err(GenericError::MissingSession);
+            };
+            let reporter = component.user.id;
+            let thread = component.channel_id;
+            match reports::file(ctx, reporter, stranger, reason, thread, guild).await? {
+                Some(_) => Ok(ButtonReply::update(
+                    "Thanks, the report was sent to the moderators",
+                )),
+                None => Ok(ButtonReply::update(
+                    "Reporting is not set up on this bot yet",
+                )),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grace_periods_read_naturally() {
+        assert_eq!(describe(Duration::from_secs(300)), "5 minutes");
+        assert_eq!(describe(Duration::from_secs(90)), "90 seconds");
+        assert_eq!(describe(Duration::from_secs(30)), "30 seconds");
+    }
+
+    #[test]
+    fn custom_ids_round_trip() {
+        let buttons = [
+            Button::Cancel,
+            Button::Next,
+            Button::Leave,
+            Button::Report(UserId::new(42)),
+            Button::Block(UserId::new(42)),
+            Button::Reveal,
+            Button::Close(UserId::new(42)),
+            Button::Review(7, Outcome::Ban),
+            Button::Review(7, Outcome::Dismiss),
+        ];
+        for button in buttons {
+            assert_eq!(Button::parse(&button.custom_id()), Some(button));
+        }
+        let menu = Menu::ReportReason(UserId::new(42));
+        assert_eq!(Menu::parse(&menu.custom_id()), Some(menu));
+    }
+
+    #[test]
+    fn malformed_custom_ids_are_rejected() {
+        for custom_id in [
+            "",
+            "report",
+            "report:",
+            "report:0",
+            "report:x",
+            "next:1",
+            "block",
+            "block:x",
+            "close",
+            "close:x",
+            "nope",
+            "review:1",
+            "review:x:ban",
+            "review:1:kick",
+        ] {
+            assert_eq!(Button::parse(custom_id), None, "{custom_id:?}");
+        }
+    }
+}
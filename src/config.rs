@@ -0,0 +1,183 @@
+//! Settings read from the environment once at startup and shared through the
+//! client's TypeMap.
+
+use std::num::NonZeroU64;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serenity::all::{ChannelId, Context, UserId};
+use serenity::prelude::TypeMapKey;
+
+use crate::media::{parse_types, MediaPolicy};
+use crate::sanitize::LinkPolicy;
+use crate::strikes::StrikePolicy;
+
+/// How long a partner left behind keeps their thread open by default.
+const DEFAULT_PARTNER_GRACE_SECS: u64 = 5 * 60;
+const DEFAULT_QUEUE_TIMEOUT_SECS: u64 = 15 * 60;
+const DEFAULT_IDLE_WARNING_SECS: u64 = 10 * 60;
+const DEFAULT_IDLE_LIMIT_SECS: u64 = 30 * 60;
+const DEFAULT_RELAY_TTL_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_REPORT_EVIDENCE: u8 = 20;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Channel that receives error reports (`BOT_OPS_CHANNEL`).
+    pub ops_channel: Option<ChannelId>,
+    /// Channel that receives reports of strangers for review
+    /// (`BOT_MOD_CHANNEL`). Reporting is off without it, since anyone who
+    /// can read the channel sees the evidence.
+    pub mod_channel: Option<ChannelId>,
+    /// How many of the last messages of a chat are kept with a report
+    /// (`REPORT_EVIDENCE_MESSAGES`, at most 100).
+    pub report_evidence: u8,
+    /// Users who may ban and unban everywhere (`BOT_ADMINS`, comma
+    /// separated ids).
+    pub bot_admins: Vec<UserId>,
+    /// What strikes lead to (`STRIKE_WARN_AT`, `STRIKE_COOLDOWN_AT`,
+    /// `STRIKE_COOLDOWN_SECS`, `STRIKE_BAN_AT`).
+    pub strike_policy: StrikePolicy,
+    /// How long the thread of someone whose partner left stays open before
+    /// it is locked and archived (`PARTNER_GRACE_SECS`).
+    pub partner_grace: Duration,
+    /// How long someone may wait for a partner before they are taken out of
+    /// the queue (`QUEUE_TIMEOUT_SECS`).
+    pub queue_timeout: Duration,
+    /// How long a pair may go without messages before they are warned
+    /// (`IDLE_WARNING_SECS`).
+    pub idle_warning: Duration,
+    /// How long a pair may go without messages before the chat is ended
+    /// (`IDLE_LIMIT_SECS`).
+    pub idle_limit: Duration,
+    /// Which links are relayed (`LINK_BLOCK_INVITES`, `LINK_ALLOWED_DOMAINS`,
+    /// `LINK_BLOCKED_DOMAINS`, `LINK_SUPPRESS_EMBEDS`).
+    pub link_policy: LinkPolicy,
+    /// How long edits, deletions and replies of a message are still carried
+    /// over to the partner (`RELAY_TTL_SECS`).
+    pub relay_ttl: Duration,
+    /// The media policy of servers that did not set their own
+    /// (`MEDIA_ENABLED`, `MEDIA_MAX_BYTES`, `MEDIA_IMAGE_ONLY`,
+    /// `MEDIA_ALLOWED_TYPES`).
+    pub media_policy: MediaPolicy,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            ops_channel: None,
+            mod_channel: None,
+            report_evidence: DEFAULT_REPORT_EVIDENCE,
+            bot_admins: vec![],
+            strike_policy: StrikePolicy::default(),
+            partner_grace: Duration::from_secs(DEFAULT_PARTNER_GRACE_SECS),
+            queue_timeout: Duration::from_secs(DEFAULT_QUEUE_TIMEOUT_SECS),
+            idle_warning: Duration::from_secs(DEFAULT_IDLE_WARNING_SECS),
+            idle_limit: Duration::from_secs(DEFAULT_IDLE_LIMIT_SECS),
+            link_policy: LinkPolicy::default(),
+            relay_ttl: Duration::from_secs(DEFAULT_RELAY_TTL_SECS),
+            media_policy: MediaPolicy::default(),
+        }
+    }
+}
+
+/// Parses `name` from the environment. Unset variables and values that do not
+/// parse fall back to the default, the latter with a warning.
+fn env_var<T: FromStr>(name: &str) -> Option<T>
+where
+    T::Err: std::fmt::Display,
+{
+    let value = std::env::var(name).ok()?;
+    match value.parse() {
+        Ok(parsed) => Some(parsed),
+        Err(e) => {
+            println!("Ignoring {name}={value:?}: {e}");
+            None
+        }
+    }
+}
+
+/// A comma separated list of domains, lowercased.
+fn domain_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|d| d.trim().trim_start_matches("*.").to_ascii_lowercase())
+        .filter(|d| !d.is_empty())
+        .collect()
+}
+
+impl Config {
+    pub fn from_env() -> Config {
+        let defaults = Config::default();
+        Config {
+            ops_channel: env_var::<NonZeroU64>("BOT_OPS_CHANNEL").map(ChannelId::from),
+            mod_channel: env_var::<NonZeroU64>("BOT_MOD_CHANNEL").map(ChannelId::from),
+            report_evidence: env_var::<u8>("REPORT_EVIDENCE_MESSAGES")
+                .map(|n| n.clamp(1, 100))
+                .unwrap_or(defaults.report_evidence),
+            bot_admins: env_var::<String>("BOT_ADMINS")
+                .map(|v| {
+                    v.split(',')
+                        .filter_map(|id| id.trim().parse::<NonZeroU64>().ok())
+                        .map(UserId::from)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            strike_policy: StrikePolicy {
+                warn_at: env_var("STRIKE_WARN_AT").unwrap_or(defaults.strike_policy.warn_at),
+                cooldown_at: env_var("STRIKE_COOLDOWN_AT")
+                    .unwrap_or(defaults.strike_policy.cooldown_at),
+                cooldown: env_var("STRIKE_COOLDOWN_SECS")
+                    .map(Duration::from_secs)
+                    .unwrap_or(defaults.strike_policy.cooldown),
+                ban_at: env_var("STRIKE_BAN_AT").unwrap_or(defaults.strike_policy.ban_at),
+            },
+            partner_grace: env_var("PARTNER_GRACE_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.partner_grace),
+            queue_timeout: env_var("QUEUE_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.queue_timeout),
+            idle_warning: env_var("IDLE_WARNING_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.idle_warning),
+            idle_limit: env_var("IDLE_LIMIT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.idle_limit),
+            link_policy: LinkPolicy {
+                block_invites: env_var("LINK_BLOCK_INVITES")
+                    .unwrap_or(defaults.link_policy.block_invites),
+                allowed_domains: env_var::<String>("LINK_ALLOWED_DOMAINS")
+                    .map(|v| domain_list(&v))
+                    .unwrap_or_default(),
+                blocked_domains: env_var::<String>("LINK_BLOCKED_DOMAINS")
+                    .map(|v| domain_list(&v))
+                    .unwrap_or_default(),
+                suppress_embeds: env_var("LINK_SUPPRESS_EMBEDS")
+                    .unwrap_or(defaults.link_policy.suppress_embeds),
+            },
+            relay_ttl: env_var("RELAY_TTL_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.relay_ttl),
+            media_policy: MediaPolicy {
+                enabled: env_var("MEDIA_ENABLED").unwrap_or(defaults.media_policy.enabled),
+                max_bytes: env_var("MEDIA_MAX_BYTES").unwrap_or(defaults.media_policy.max_bytes),
+                image_only: env_var("MEDIA_IMAGE_ONLY").unwrap_or(defaults.media_policy.image_only),
+                allowed_types: env_var::<String>("MEDIA_ALLOWED_TYPES")
+                    .map(|v| parse_types(&v))
+                    .unwrap_or_default(),
+            },
+        }
+    }
+}
+
+impl TypeMapKey for Config {
+    type Value = Arc<Config>;
+}
+
+pub async fn get_config(ctx: &Context) -> Arc<Config> {
+    let data = ctx.data.read().await;
+    data.get::<Config>()
+        .cloned()
+        .expect("Config is inserted before the client starts")
+}
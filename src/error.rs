@@ -0,0 +1,217 @@
+//! The bot's error type, what users are told when something fails, and the
+//! reports that go to the bot-ops channel.
+
+use serenity::all::{
+    ChannelId, Colour, CommandInteraction, ComponentInteraction, Context, CreateEmbed,
+    CreateMessage, Interaction, ModelError, UserId,
+};
+use serenity::http::HttpError;
+
+use crate::config::get_config;
+use crate::store::StoreError;
+
+#[derive(Debug)]
+pub enum GenericError {
+    RedisError(redis::RedisError),
+    SerenityError(serenity::Error),
+    // SerenityJsonError(serenity::model::),
+    SerdeJsonError(serde_json::Error),
+    StoreError(StoreError),
+    /// The user acted on a chat they are not (or no longer) part of.
+    MissingSession,
+    /// Discord refused a request because the bot lacks a permission.
+    PermissionDenied(serenity::Error),
+    /// Discord refused a request because the bot is sending too many.
+    RateLimited(serenity::Error),
+}
+
+impl std::fmt::Display for GenericError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GenericError::RedisError(e) => write!(f, "redis error: {e}"),
+            GenericError::SerenityError(e) => write!(f, "discord error: {e}"),
+            GenericError::SerdeJsonError(e) => write!(f, "serialization error: {e}"),
+            GenericError::StoreError(e) => write!(f, "session store error: {e}"),
+            GenericError::MissingSession => write!(f, "no session for this user"),
+            GenericError::PermissionDenied(e) => write!(f, "missing permission: {e}"),
+            GenericError::RateLimited(e) => write!(f, "rate limited: {e}"),
+        }
+    }
+}
+
+impl GenericError {
+    /// What to tell the user whose interaction failed; the details only go
+    /// to the log.
+    pub fn user_message(&self) -> &'static str {
+        match self {
+            GenericError::RedisError(_) | GenericError::StoreError(_) => {
+                "Could not reach the session storage, please try again in a moment"
+            }
+            GenericError::SerenityError(_) => {
+                "Discord did not accept the request, please try again"
+            }
+            GenericError::SerdeJsonError(_) => "Something went wrong on our side, please try again",
+            GenericError::MissingSession => "You are not in a chat right now",
+            GenericError::PermissionDenied(_) => {
+                "I am missing a permission for that, please ask a server admin to check my role"
+            }
+            GenericError::RateLimited(_) => {
+                "Discord is asking me to slow down, please try again in a few seconds"
+            }
+        }
+    }
+
+    /// Whether the bot-ops channel should hear about this. Users poking at a
+    /// chat that already ended is expected and not worth a report.
+    fn is_reportable(&self) -> bool {
+        !matches!(self, GenericError::MissingSession)
+    }
+}
+
+impl From<redis::RedisError> for GenericError {
+    fn from(error: redis::RedisError) -> Self {
+        GenericError::RedisError(error)
+    }
+}
+
+impl From<serenity::Error> for GenericError {
+    fn from(error: serenity::Error) -> Self {
+        match &error {
+            serenity::Error::Http(HttpError::UnsuccessfulRequest(res)) => {
+                match res.status_code.as_u16() {
+                    403 => GenericError::PermissionDenied(error),
+                    429 => GenericError::RateLimited(error),
+                    _ => GenericError::SerenityError(error),
+                }
+            }
+            serenity::Error::Model(ModelError::InvalidPermissions { .. }) => {
+                GenericError::PermissionDenied(error)
+            }
+            _ => GenericError::SerenityError(error),
+        }
+    }
+}
+
+impl From<StoreError> for GenericError {
+    fn from(error: StoreError) -> Self {
+        GenericError::StoreError(error)
+    }
+}
+
+impl From<serde_json::Error> for GenericError {
+    fn from(error: serde_json::Error) -> Self {
+        GenericError::SerdeJsonError(error)
+    }
+}
+
+/// Where an error happened, for the bot-ops report.
+#[derive(Debug)]
+pub struct ErrorContext {
+    event: String,
+    user: Option<UserId>,
+    channel: Option<ChannelId>,
+}
+
+impl ErrorContext {
+    pub fn new(event: impl Into<String>) -> ErrorContext {
+        ErrorContext {
+            event: event.into(),
+            user: None,
+            channel: None,
+        }
+    }
+
+    pub fn user(mut self, user: UserId) -> ErrorContext {
+        self.user = Some(user);
+        self
+    }
+
+    pub fn channel(mut self, channel: ChannelId) -> ErrorContext {
+        self.channel = Some(channel);
+        self
+    }
+
+    pub fn command(command: &CommandInteraction) -> ErrorContext {
+        ErrorContext::new(format!("/{}", command.data.name))
+            .user(command.user.id)
+            .channel(command.channel_id)
+    }
+
+    pub fn component(component: &ComponentInteraction) -> ErrorContext {
+        ErrorContext::new(format!("button {}", component.data.custom_id))
+            .user(component.user.id)
+            .channel(component.channel_id)
+    }
+
+    pub fn interaction(interaction: &Interaction) -> ErrorContext {
+        match interaction {
+            Interaction::Command(command) => ErrorContext::command(command),
+            Interaction::Component(component) => ErrorContext::component(component),
+            _ => ErrorContext::new("interaction"),
+        }
+    }
+}
+
+/// Longest error text put into a report; embed descriptions cap at 4096.
+const REPORT_DETAIL_CHARS: usize = 1000;
+
+fn summarize(error: &GenericError) -> String {
+    let detail = error.to_string();
+    if detail.chars().count() <= REPORT_DETAIL_CHARS {
+        return detail;
+    }
+    let mut summary: String = detail.chars().take(REPORT_DETAIL_CHARS).collect();
+    summary.push('…');
+    summary
+}
+
+/// Logs `error` and, if a bot-ops channel is configured, posts a summary of
+/// it there. Never fails: a report that cannot be sent is only logged.
+pub async fn report_error(ctx: &Context, context: &ErrorContext, error: &GenericError) {
+    println!("Error in {}: {:?}", context.event, error);
+    if !error.is_reportable() {
+        return;
+    }
+    let Some(ops) = get_config(ctx).await.ops_channel else {
+        return;
+    };
+
+    let mut embed = CreateEmbed::new()
+        .title(format!("Error in {}", context.event))
+        .description(format!("```{}```", summarize(error)))
+        .colour(Colour::RED);
+    if let Some(user) = context.user {
+        embed = embed.field("User", format!("<@{user}> ({user})"), true);
+    }
+    if let Some(channel) = context.channel {
+        embed = embed.field("Channel", format!("<#{channel}> ({channel})"), true);
+    }
+    if let Err(e) = ops
+        .send_message(&ctx.http, CreateMessage::new().embed(embed))
+        .await
+    {
+        println!("Error reporting to bot-ops channel: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_errors_are_cut_for_the_report() {
+        let error = GenericError::SerenityError(serenity::Error::Other("x"));
+        assert_eq!(summarize(&error), "discord error: x");
+
+        let long = "y".repeat(REPORT_DETAIL_CHARS * 2).leak();
+        let summary = summarize(&GenericError::SerenityError(serenity::Error::Other(long)));
+        assert_eq!(summary.chars().count(), REPORT_DETAIL_CHARS + 1);
+        assert!(summary.ends_with('…'));
+    }
+
+    #[test]
+    fn missing_sessions_are_not_reported() {
+        assert!(!GenericError::MissingSession.is_reportable());
+        assert!(GenericError::SerenityError(serenity::Error::Other("x")).is_reportable());
+    }
+}
@@ -1,100 +1,340 @@
 // use std::env;
 
+mod bans;
 mod commands;
+mod components;
+mod config;
+mod error;
+mod matchmaking;
+mod media;
+mod persona;
+mod relay;
+mod reports;
+mod sanitize;
+mod scheduler;
+mod store;
+mod strikes;
+mod webhooks;
 
-use serde::{Deserialize, Serialize};
 use serenity::all::{
-    ActivityData, ButtonStyle, ChannelId, ChannelType, Command, CommandInteraction,
-    CreateAttachment, CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage,
-    CreateMessage, CreateThread, EditInteractionResponse, Guild, GuildChannel, Interaction,
-    Message, PartialGuildChannel, ResolvedValue, UserId,
+    ActivityData, ChannelId, ChannelType, CommandInteraction, CreateAttachment, CreateMessage,
+    CreateThread, EditThread, Guild, GuildChannel, GuildId, Interaction, Member, Message,
+    MessageId, MessageUpdateEvent, PartialGuildChannel, Reaction, ResolvedValue, TypingStartEvent,
+    UnavailableGuild, UserId,
 };
 use serenity::async_trait;
-use serenity::futures::StreamExt;
+use serenity::http::HttpError;
 use serenity::model::gateway::Ready;
 use serenity::prelude::*;
 
-use redis::Commands;
+use config::{get_config, Config};
+use error::{report_error, ErrorContext, GenericError};
+use matchmaking::{shared_interests, Decision, Entry, Event, Queue, Rejection};
+use redis::aio::ConnectionManager;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use store::{MemoryStore, RedisStore, SessionStore, StoreError, User};
+
+struct Handler {
+    /// Set once the startup reconciliation pass has run and the scheduler is
+    /// started, so gateway reconnects firing `ready` again do not repeat it.
+    reconciled: AtomicBool,
+}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct User {
-    id: UserId,
-    channel: ChannelId,
+/// Seconds a newcomer waits for an interest match when `/start` is run without `wait`.
+const DEFAULT_WAIT_SECS: u64 = 10;
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// How someone wants to be matched. Remembered per user, so a search started
+/// from a finished chat works the same way as their last `/start`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StartOptions {
     interests: Vec<String>,
-    partner: Option<UserId>,
-    partner_channel: Option<ChannelId>,
+    wait: u64,
+    anyone: bool,
 }
 
-struct Handler;
+impl Default for StartOptions {
+    fn default() -> StartOptions {
+        StartOptions {
+            interests: vec![],
+            wait: DEFAULT_WAIT_SECS,
+            anyone: false,
+        }
+    }
+}
 
-#[derive(Debug)]
-enum GenericError {
-    RedisError(redis::RedisError),
-    SerenityError(serenity::Error),
-    // SerenityJsonError(serenity::model::),
-    SerdeJsonError(serde_json::Error),
+fn start_options(command: &CommandInteraction) -> StartOptions {
+    let StartOptions {
+        mut interests,
+        mut wait,
+        mut anyone,
+    } = StartOptions::default();
+    for option in command.data.options() {
+        match (option.name, option.value) {
+            ("interest", ResolvedValue::String(interest)) => {
+                for x in interest.split(",").map(|x| x.trim().to_lowercase()) {
+                    if !x.is_empty() && !interests.contains(&x) {
+                        interests.push(x);
+                    }
+                }
+            }
+            ("wait", ResolvedValue::Integer(secs)) => {
+                wait = secs.max(0) as u64;
+            }
+            ("anyone", ResolvedValue::Boolean(flag)) => {
+                anyone = flag;
+            }
+            _ => {}
+        }
+    }
+    StartOptions {
+        interests,
+        wait,
+        anyone,
+    }
 }
 
-impl From<redis::RedisError> for GenericError {
-    fn from(error: redis::RedisError) -> Self {
-        GenericError::RedisError(error)
+/// When a user starts accepting partners without shared interests.
+fn wait_deadline(interests: &[String], anyone: bool, wait: u64, now: u64) -> u64 {
+    // Without interests there is nothing to hold out for, so the window is
+    // already over and the user can be paired with anyone straight away.
+    if interests.is_empty() || anyone {
+        now
+    } else {
+        now + wait
     }
 }
 
-impl From<serenity::Error> for GenericError {
-    fn from(error: serenity::Error) -> Self {
-        GenericError::SerenityError(error)
+fn connected_message(shared: &[String]) -> String {
+    if shared.is_empty() {
+        "You are connected to user".to_string()
+    } else {
+        format!(
+            "You are connected to user\nYou both like: {}",
+            shared.join(", ")
+        )
     }
 }
 
-impl From<serde_json::Error> for GenericError {
-    fn from(error: serde_json::Error) -> Self {
-        GenericError::SerdeJsonError(error)
+/// Discord's JSON error code for a channel that does not exist (anymore).
+const UNKNOWN_CHANNEL: isize = 10003;
+
+/// How often `try_match` re-reads the queue after losing a pairing race.
+const MATCH_ATTEMPTS: usize = 5;
+
+async fn announce_match(ctx: &Context, user: &User, partner: &User) -> Result<(), GenericError> {
+    let connected_msg = connected_message(&shared_interests(&user.interests, &partner.interests));
+    user.channel
+        .send_message(
+            &ctx.http,
+            components::connected_message(&connected_msg, partner),
+        )
+        .await?;
+    partner
+        .channel
+        .send_message(
+            &ctx.http,
+            components::connected_message(&connected_msg, user),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Matchmaking view of everyone in `waiting` plus `subject`'s own pairing.
+fn snapshot(subject: Option<&User>, waiting: &[User]) -> Queue {
+    let pair = subject.and_then(|u| Some((u.id.get(), u.partner?.get())));
+    Queue::new(waiting.iter().map(User::entry).collect(), pair)
+}
+
+#[derive(Debug)]
+enum MatchOutcome {
+    /// This call paired the two users, who still need to be told.
+    Paired(Box<User>, Box<User>),
+    /// Someone else paired the user meanwhile.
+    AlreadyPaired,
+    /// Nobody suitable is waiting, or the user left the queue.
+    Waiting,
+}
+
+/// Store-side half of `try_match`: claims the best waiting partner for a
+/// queued user, retrying when a concurrent pairing takes the candidate first.
+/// Users blocked by or blocking the user are never considered, and neither
+/// is anyone banned from the other side's server.
+async fn claim_match(
+    sessions: &dyn SessionStore,
+    user_id: UserId,
+    now: u64,
+) -> Result<MatchOutcome, StoreError> {
+    let blocked = sessions.blocks_involving(user_id).await?;
+    for _ in 0..MATCH_ATTEMPTS {
+        let Some(user) = sessions.load_user(user_id).await? else {
+            // Cancelled while waiting.
+            return Ok(MatchOutcome::Waiting);
+        };
+        if user.partner.is_some() {
+            return Ok(MatchOutcome::AlreadyPaired);
+        }
+
+        let mut waiting = vec![];
+        for candidate in sessions.waiting_users().await? {
+            if !blocked.contains(&candidate.id)
+                && !banned_between(sessions, &user, &candidate, now).await?
+            {
+                waiting.push(candidate);
+            }
+        }
+        let decision = snapshot(Some(&user), &waiting).decide(&Event::Rematch(user_id.get()), now);
+        let Decision::Pair(_, partner) = decision else {
+            return Ok(MatchOutcome::Waiting);
+        };
+        let Some(free_user) = waiting.iter().find(|u| u.id.get() == partner) else {
+            return Ok(MatchOutcome::Waiting);
+        };
+        if sessions.pair(&user, free_user, now).await? {
+            return Ok(MatchOutcome::Paired(
+                Box::new(user),
+                Box::new(free_user.clone()),
+            ));
+        }
+        // Either side was taken by a concurrent pairing, look again.
     }
+    Ok(MatchOutcome::Waiting)
 }
 
-async fn matcher(
+/// Whether either of `a` and `b` is banned in the other's server.
+async fn banned_between(
+    sessions: &dyn SessionStore,
+    a: &User,
+    b: &User,
+    now: u64,
+) -> Result<bool, StoreError> {
+    Ok(sessions.active_ban(a.id, b.guild, now).await?.is_some()
+        || sessions.active_ban(b.id, a.guild, now).await?.is_some())
+}
+
+/// Tries to pair a queued user with the best waiting partner. Returns `true`
+/// once the user is paired, whether by this call or by someone else meanwhile.
+async fn try_match(
     ctx: &Context,
-    command: &CommandInteraction,
-    redis_connection: &mut redis::Connection,
-) -> Result<String, GenericError> {
-    let connecting: String = redis_connection.get("connecting")?;
-    let connected: String = redis_connection.get("connected")?;
-    println!("Connecting: {:?}", connecting);
-    println!("Connected: {:?}", connected);
+    user_id: UserId,
+    sessions: &dyn SessionStore,
+) -> Result<bool, GenericError> {
+    match claim_match(sessions, user_id, now_secs()).await? {
+        MatchOutcome::Paired(user, partner) => {
+            announce_match(ctx, &user, &partner).await?;
+            Ok(true)
+        }
+        MatchOutcome::AlreadyPaired => Ok(true),
+        MatchOutcome::Waiting => Ok(false),
+    }
+}
 
-    let mut connecting_vec: Vec<User> = serde_json::from_str(&connecting)?;
-    println!("Connecting vec: {:?}", connecting_vec);
-    let mut connected_vec: Vec<User> = serde_json::from_str(&connected)?;
+/// What came of asking to be matched.
+#[derive(Debug)]
+enum Admission {
+    /// The user has a new thread and is waiting or already paired there.
+    Queued(String),
+    /// The user was turned away and nothing changed.
+    Refused(String),
+}
+
+impl Admission {
+    fn message(self) -> String {
+        match self {
+            Admission::Queued(msg) | Admission::Refused(msg) => msg,
+        }
+    }
+}
 
-    if let Some(_val) = connecting_vec.iter().find(|u| u.id == command.user.id) {
-        println!("You are already in queue");
+/// Why `user_id` may not chat with strangers in `guild` right now: an
+/// active ban or a strike cooldown.
+async fn barred(
+    ctx: &Context,
+    user_id: UserId,
+    guild: Option<GuildId>,
+    sessions: &dyn SessionStore,
+    now: u64,
+) -> Result<Option<String>, GenericError> {
+    if let Some(ban) = sessions.active_ban(user_id, guild, now).await? {
+        return Ok(Some(ban.refusal()));
+    }
+    let strikes = sessions.strikes(user_id).await?;
+    let policy = &get_config(ctx).await.strike_policy;
+    Ok(policy
+        .restriction(&strikes, now)
+        .map(|restriction| restriction.refusal(strikes.len())))
+}
 
-        return Ok("You are already in queue".to_string());
+async fn matcher(
+    ctx: &Context,
+    user_id: UserId,
+    guild: Option<GuildId>,
+    parent: ChannelId,
+    options: StartOptions,
+    sessions: &dyn SessionStore,
+) -> Result<Admission, GenericError> {
+    let now = now_secs();
+    if let Some(refusal) = barred(ctx, user_id, guild, sessions, now).await? {
+        return Ok(Admission::Refused(refusal));
     }
+    let StartOptions {
+        interests: insts,
+        wait,
+        anyone,
+    } = options.clone();
+    let wait_until = wait_deadline(&insts, anyone, wait, now);
+
+    // Only admission is decided here; who to pair with is decided again
+    // against a fresh snapshot once the user is in the queue.
+    let existing = sessions.load_user(user_id).await?;
+    let existing_waiting: Vec<User> = existing
+        .iter()
+        .filter(|u| u.partner.is_none())
+        .cloned()
+        .collect();
+    let join = Event::Join(Entry {
+        id: user_id.get(),
+        interests: insts.clone(),
+        wait_until,
+        anyone,
+    });
+    match snapshot(existing.as_ref(), &existing_waiting).decide(&join, now) {
+        Decision::Reject(_, Rejection::AlreadyConnected) => {
+            println!("You are already connected");
+            let channel = existing.map(|u| u.channel).unwrap_or(parent);
+            let msg_str = format!("You are already connected -> <#{}>", channel);
+            return Ok(Admission::Refused(msg_str));
+        }
+        Decision::Reject(..) => {
+            println!("You are already in queue");
 
-    if let Some(_val) = connected_vec.iter().find(|u| u.id == command.user.id) {
-        println!("You are already connected");
-        let msg_str = format!(
-            "You are already connected -> <#{}>",
-            _val.channel.to_string()
-        );
-        return Ok(msg_str);
+            return Ok(Admission::Refused("You are already in queue".to_string()));
+        }
+        _ => {}
     }
 
-    let thread_name = format!(
-        "{}{}",
-        command.user.id.to_string(),
-        command.user.global_name.clone().unwrap()
-    );
+    let thread_name = format!("Anonymous chat #{}", sessions.next_chat_number().await?);
     let x = CreateThread::new(thread_name)
         .invitable(false)
         .kind(ChannelType::PrivateThread)
         .auto_archive_duration(serenity::all::AutoArchiveDuration::OneHour);
-    let _res = ctx
-        .http()
-        .create_thread(command.channel_id, &x, Some("Hello"))
-        .await?;
+    let _res = ctx.http().create_thread(parent, &x, Some("Hello")).await?;
 
     println!("Thread created: {:?}", _res);
     ctx.http()
@@ -104,314 +344,448 @@ async fn matcher(
             &CreateMessage::new().content("Hello"),
         )
         .await?;
-    _res.id
-        .add_thread_member(&ctx.http, command.user.id)
-        .await?;
+    _res.id.add_thread_member(&ctx.http, user_id).await?;
 
-    // println!("interest: {:?}", command.data.options);
-
-    println!("Interests: {:?}", command.data.options);
-    let insts = match command.data.options.len() {
-        0 => vec![],
-        _ => match command.data.options()[0].value.clone() {
-            ResolvedValue::String(interest) => interest
-                .split(",")
-                .map(|x| x.trim().to_string())
-                .collect::<Vec<_>>(),
-            _ => vec![],
-        },
-    };
-    // println!("Interests: {:?}", insts);
-
-    let mut user = User {
-        id: command.user.id,
+    println!("Interests: {:?}", insts);
+    let user = User {
+        id: user_id,
         channel: _res.id,
+        guild: Some(_res.guild_id),
+        lobby: Some(parent),
         interests: insts,
         partner: None,
         partner_channel: None,
+        wait_until,
+        wait,
+        anyone,
+        last_active: now,
+        idle_warned: false,
     };
     _res.id
         .say(&ctx.http, "Waiting for user to connect")
         .await?;
 
-    if connecting_vec.len() > 0 {
-        connecting_vec.sort_by(|a, b| b.interests.len().cmp(&a.interests.len()));
-        let mut free_user = connecting_vec[0].clone();
-        connecting_vec.remove(0);
-        free_user.partner = Some(command.user.id);
-        user.partner = Some(free_user.id);
-        free_user.partner_channel = Some(user.channel);
-        user.partner_channel = Some(free_user.channel);
+    // Only a search that was let in becomes the one Next repeats.
+    sessions.save_search(user_id, &options).await?;
+    if !sessions.enqueue(&user, now_millis()).await? {
+        // A concurrent /start from the same user got in first.
+        _res.id.delete(&ctx.http).await?;
+        return Ok(Admission::Refused("You are already in queue".to_string()));
+    }
+
+    if try_match(ctx, user.id, sessions).await? {
+        let msg_str = format!("You are connected to user -> <#{}>", _res.id);
+        return Ok(Admission::Queued(msg_str));
+    }
+
+    if user.wait_until > now {
+        schedule_expiry(ctx, user.id, wait);
+    }
+
+    let msg_str = format!("You can chat with your Partner here -->  <#{}>", _res.id);
+    Ok(Admission::Queued(msg_str))
+}
+
+fn schedule_expiry(ctx: &Context, user_id: UserId, wait: u64) {
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(wait)).await;
+        if let Err(e) = expire_wait(&ctx, user_id).await {
+            report_error(&ctx, &ErrorContext::new("wait expiry").user(user_id), &e).await;
+        }
+    });
+}
+
+/// Runs once a waiting user's interest window is over and pairs them with
+/// anyone who is also open to any partner.
+async fn expire_wait(ctx: &Context, user_id: UserId) -> Result<(), GenericError> {
+    let sessions = get_sessions(ctx).await;
+    let Some(user) = sessions.load_user(user_id).await? else {
+        // Cancelled while waiting.
+        return Ok(());
+    };
+    if user.partner.is_some() {
+        return Ok(());
+    }
+    // A later search or skip set a new deadline, whose own task handles it.
+    if user.wait_until > now_secs() {
+        return Ok(());
+    }
+
+    if !try_match(ctx, user_id, &*sessions).await? {
         user.channel
-            .say(&ctx.http, "You are connected to user")
-            .await?;
-        free_user
-            .channel
-            .say(&ctx.http, "You are connected to user")
+            .say(
+                &ctx.http,
+                "No one with matching interests yet, connecting you to anyone",
+            )
             .await?;
+    }
+    Ok(())
+}
 
-        connected_vec.push(free_user.clone());
-        connected_vec.push(user.clone());
-        let connected_ser = serde_json::to_string(&connected_vec)?;
-        let connecting_ser = serde_json::to_string(&connecting_vec)?;
-        redis_connection.set("connected", connected_ser)?;
-        redis_connection.set("connecting", connecting_ser)?;
-        redis_connection.set(command.user.id.to_string(), free_user.id.to_string())?;
-        redis_connection.set(free_user.id.to_string(), command.user.id.to_string())?;
-        redis_connection.set(free_user.channel.to_string(), user.channel.to_string())?;
-        redis_connection.set(user.channel.to_string(), free_user.channel.to_string())?;
+/// Store-side half of ending a session. Returns the user and their partner
+/// only to the caller that actually ended it.
+async fn end_pairing(
+    sessions: &dyn SessionStore,
+    user_id: UserId,
+) -> Result<Option<(User, User)>, StoreError> {
+    let Some(u) = sessions.load_user(user_id).await? else {
+        return Ok(None);
+    };
+    let Some(partner) = u.partner else {
+        return Ok(None);
+    };
+    let Some(partner) = sessions.load_user(partner).await? else {
+        return Ok(None);
+    };
 
-        let msg_str = format!("You are connected to user -> <#{}>", _res.id.to_string());
-        return Ok(msg_str);
+    if sessions.unpair(&u, &partner).await? {
+        Ok(Some((u, partner)))
+    } else {
+        Ok(None)
     }
+}
 
-    connecting_vec.push(user);
+async fn cancel_wait(
+    ctx: &Context,
+    user_id: UserId,
+    sessions: &dyn SessionStore,
+) -> Result<String, GenericError> {
+    let user = sessions.load_user(user_id).await?;
+    let waiting: Vec<User> = user
+        .iter()
+        .filter(|u| u.partner.is_none())
+        .cloned()
+        .collect();
+    let cancel = Event::Cancel(user_id.get());
+    match (
+        snapshot(user.as_ref(), &waiting).decide(&cancel, now_secs()),
+        user,
+    ) {
+        (Decision::Remove(_), Some(u)) => {
+            if sessions.dequeue(&u).await? {
+                u.channel.delete(&ctx.http).await?;
+            }
+            Ok("Successfully cancelled the request".to_string())
+        }
+        (Decision::Unpair(..), Some(u)) => {
+            if let Some(own) = leave_chat(ctx, u.id, sessions).await? {
+                close_thread(ctx, own).await?;
+            }
+            Ok("Successfully cancelled the request".to_string())
+        }
+        _ => {
+            //replace /start with command id of start command
+            Ok("You are not in queue. \n Use /start to connect to stranger".to_string())
+        }
+    }
+}
 
-    // println!("Subscribed to user: {:?}", command.user.id);
-    let connecting_ser = serde_json::to_string(&connecting_vec)?;
-    redis_connection.set("connecting", connecting_ser)?;
-    let msg_str = format!("You can chat with your Partner here -->  <#{}>", _res.id);
-    Ok(msg_str)
+/// Ends `user_id`'s session from their side and tells the partner. Returns
+/// the leaver's own thread, which the caller closes once it has answered
+/// the interaction.
+async fn leave_chat(
+    ctx: &Context,
+    user_id: UserId,
+    sessions: &dyn SessionStore,
+) -> Result<Option<ChannelId>, GenericError> {
+    let user = sessions.load_user(user_id).await?;
+    let leave = Event::Leave(user_id.get());
+    let Decision::Unpair(..) = snapshot(user.as_ref(), &[]).decide(&leave, now_secs()) else {
+        return Ok(None);
+    };
+    let Some((u, partner)) = end_pairing(sessions, user_id).await? else {
+        return Ok(None);
+    };
+    close_partner_thread(ctx, &partner, u.id).await?;
+    Ok(Some(u.channel))
 }
 
-async fn disconnect_users(
-    user1: UserId,
+/// Blocks `user_id`'s partner for good and ends their chat. Returns the
+/// blocker's own thread, which the caller closes once it has answered the
+/// interaction.
+async fn block_partner(
     ctx: &Context,
-    redis_connection: &mut redis::Connection,
-) -> Result<(), GenericError> {
-    let connected: String = redis_connection.get("connected")?;
-    let mut connected_vec: Vec<User> = serde_json::from_str(&connected)?;
-
-    if let Some(u) = connected_vec.iter().find(|u| u.id == user1) {
-        let user2 = u.partner.unwrap();
-        let user2_channel = u.partner_channel.unwrap();
-        let user1_channel = u.channel;
-        user2_channel.delete(&ctx.http).await?;
-        user1_channel.delete(&ctx.http).await?;
-        redis_connection.del(user1.to_string())?;
-        redis_connection.del(user2.to_string())?;
-        redis_connection.del(user1_channel.to_string())?;
-        redis_connection.del(user2_channel.to_string())?;
-
-        connected_vec.retain(|u| u.id != user1);
-        connected_vec.retain(|u| u.id != user2);
-        let connected_ser = serde_json::to_string(&connected_vec)?;
-        redis_connection.set("connected", connected_ser)?;
+    user_id: UserId,
+    sessions: &dyn SessionStore,
+) -> Result<Option<ChannelId>, GenericError> {
+    let Some(partner) = sessions.load_user(user_id).await?.and_then(|u| u.partner) else {
+        return Ok(None);
+    };
+    sessions.block(user_id, partner).await?;
+    leave_chat(ctx, user_id, sessions).await
+}
+
+/// Moves `user_id` on to a new stranger. The partner is told and their thread
+/// closes, while the caller keeps their thread and interests and goes
+/// straight back into matching.
+async fn skip_partner(
+    ctx: &Context,
+    user_id: UserId,
+    sessions: &dyn SessionStore,
+) -> Result<String, GenericError> {
+    let user = sessions.load_user(user_id).await?;
+    let leave = Event::Leave(user_id.get());
+    let decision = snapshot(user.as_ref(), &[]).decide(&leave, now_secs());
+    let (Decision::Unpair(..), Some(user)) = (decision, user) else {
+        return Err(GenericError::MissingSession);
+    };
+    let partner = match user.partner {
+        Some(partner) => sessions.load_user(partner).await?,
+        None => None,
+    };
+    let Some(partner) = partner else {
+        return Err(GenericError::MissingSession);
+    };
+
+    let now = now_secs();
+    // Skipping puts them back in the queue, which a ban or cooldown that
+    // arrived mid-chat rules out just as it would a fresh /start.
+    if let Some(refusal) = barred(ctx, user_id, user.guild, sessions, now).await? {
+        expel(ctx, &user, sessions).await?;
+        return Ok(refusal);
+    }
+    let user = User {
+        wait_until: wait_deadline(&user.interests, user.anyone, user.wait, now),
+        last_active: now,
+        ..user
+    };
+    if !sessions.skip(&user, &partner, now_millis()).await? {
+        return Err(GenericError::MissingSession);
     }
+    close_partner_thread(ctx, &partner, user.id).await?;
+
+    user.channel
+        .say(&ctx.http, "Stranger skipped. Waiting for user to connect")
+        .await?;
+    if try_match(ctx, user.id, sessions).await? {
+        return Ok("You are connected to a new stranger".to_string());
+    }
+    if user.wait_until > now {
+        schedule_expiry(ctx, user.id, user.wait_until - now);
+    }
+    Ok("Looking for a new stranger".to_string())
+}
+
+/// Tells the partner left behind that the chat is over and offers them a
+/// way on. Their thread stays readable for the configured grace period
+/// before it is locked and archived.
+async fn close_partner_thread(
+    ctx: &Context,
+    partner: &User,
+    stranger: UserId,
+) -> Result<(), GenericError> {
+    let grace = get_config(ctx).await.partner_grace;
+    partner
+        .channel
+        .send_message(
+            &ctx.http,
+            components::partner_left_message(partner.id, stranger, grace),
+        )
+        .await?;
+    schedule_close(ctx, partner.channel, grace);
     Ok(())
 }
 
-async fn cancel_wait(
+fn schedule_close(ctx: &Context, thread: ChannelId, after: Duration) {
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(after).await;
+        if let Err(e) = close_thread(&ctx, thread).await {
+            report_error(&ctx, &ErrorContext::new("thread close").channel(thread), &e).await;
+        }
+    });
+}
+
+/// Locks and archives a chat thread, keeping it readable to its owner.
+async fn close_thread(ctx: &Context, thread: ChannelId) -> Result<(), GenericError> {
+    thread
+        .edit_thread(&ctx.http, EditThread::new().locked(true).archived(true))
+        .await?;
+    Ok(())
+}
+
+/// Takes `user` out of their chat or the queue and closes their thread, for
+/// when they may no longer chat.
+async fn expel(
     ctx: &Context,
-    command: &CommandInteraction,
-    redis_connection: &mut redis::Connection,
-) -> Result<String, GenericError> {
-    let connecting: String = redis_connection.get("connecting")?;
-    let mut connecting_vec: Vec<User> = serde_json::from_str(&connecting)?;
-    let connected: String = redis_connection.get("connected")?;
-    let connected_vec: Vec<User> = serde_json::from_str(&connected)?;
-
-    if let Some(u) = connecting_vec.iter().find(|u| u.id == command.user.id) {
-        let user1_channel = u.channel;
-        user1_channel.delete(&ctx.http).await?;
-        connecting_vec.retain(|u| u.id != command.user.id);
-        let connecting_ser = serde_json::to_string(&connecting_vec)?;
-        redis_connection.set("connecting", connecting_ser)?;
-        Ok("Successfully cancelled the request".to_string())
-    } else if let Some(u) = connected_vec.iter().find(|u| u.id == command.user.id) {
-        println!("User not found in connecting");
-        disconnect_users(u.id, ctx, redis_connection).await?;
-        Ok("Successfully cancelled the request".to_string())
-    } else {
-        //replace /start with command id of start command
-        Ok("You are not in queue. \n Use /start to connect to stranger".to_string())
+    user: &User,
+    sessions: &dyn SessionStore,
+) -> Result<(), GenericError> {
+    end_session(ctx, user, sessions).await?;
+    close_thread(ctx, user.channel).await
+}
+
+/// Sends `user` a direct message. Users who turned those off simply miss it.
+async fn notify_user(ctx: &Context, user: UserId, content: &str) {
+    if let Ok(dm) = user.create_dm_channel(&ctx.http).await {
+        dm.say(&ctx.http, content).await.ok();
     }
 }
 
-fn get_redis_connection() -> Result<redis::Connection, redis::RedisError> {
-    let client = redis::Client::open(
-        std::env::var("REDIS_URL").expect("REDIS_URL must be set in the environment"),
-        // "redis://127.0.0.1:6379",
-    )?;
-    let mut con: redis::Connection = client.get_connection()?;
-    let _ = con.set("con", "true")?;
-    Ok(con)
+/// Cleans up after a private thread disappeared or was archived.
+async fn end_session_for_channel(
+    ctx: &Context,
+    channel: ChannelId,
+    sessions: &dyn SessionStore,
+) -> Result<(), GenericError> {
+    match sessions.user_by_channel(channel).await? {
+        Some(user) => end_session(ctx, &user, sessions).await,
+        None => Ok(()),
+    }
 }
 
-#[derive(Serialize)]
-struct MapForThread;
+/// Ends `user`'s session from outside the chat: a waiting user leaves the
+/// queue, a connected user's partner is told they left.
+async fn end_session(
+    ctx: &Context,
+    user: &User,
+    sessions: &dyn SessionStore,
+) -> Result<(), GenericError> {
+    if user.partner.is_none() {
+        sessions.dequeue(user).await?;
+        return Ok(());
+    }
+    if let Some((_, partner)) = end_pairing(sessions, user.id).await? {
+        close_partner_thread(ctx, &partner, user.id).await?;
+    }
+    Ok(())
+}
 
-async fn try_interaction_create(
-    ctx: Context,
-    interaction: Interaction,
+/// Ends every session whose thread is in a server the bot was removed from.
+/// Partners in other servers are told; threads in `guild` are out of reach.
+async fn end_sessions_in_guild(
+    ctx: &Context,
+    guild: GuildId,
+    sessions: &dyn SessionStore,
 ) -> Result<(), GenericError> {
-    if let Interaction::Command(command) = interaction {
-        let redis_connection = get_redis_connection();
-
-        match &command.channel.clone().unwrap().kind {
-            ChannelType::PrivateThread => {
-                if command.data.name.as_str() != "leave" {
-                    command
-                        .create_response(
-                            ctx,
-                            CreateInteractionResponse::Message(
-                                CreateInteractionResponseMessage::new()
-                                    .content("You can only use /leave command in the thread")
-                                    .ephemeral(true),
-                            ),
-                        )
-                        .await?;
-
-                    return Ok(());
-                }
-            }
-            _ => {}
+    for (_, user) in sessions.all_users().await? {
+        let Some(user) = user.filter(|u| u.guild == Some(guild)) else {
+            continue;
+        };
+        if user.partner.is_none() {
+            sessions.dequeue(&user).await?;
+            continue;
+        }
+        let Some((_, partner)) = end_pairing(sessions, user.id).await? else {
+            continue;
+        };
+        if partner.guild != Some(guild) {
+            close_partner_thread(ctx, &partner, user.id).await?;
         }
+    }
+    Ok(())
+}
 
-        match &redis_connection {
-            Ok(_con) => {
-                println!("Connected to redis");
-            }
-            Err(e) => {
-                println!("Error connecting to redis : {:?}", e);
-                command
-                    .create_response(
-                        ctx.http,
-                        CreateInteractionResponse::Message(
-                            CreateInteractionResponseMessage::new()
-                                .content("There was some error connecting to redis"),
-                        ),
-                    )
-                    .await?;
-                return Ok(());
-            }
+/// Whether a private thread is still there. Only a definite "Unknown Channel"
+/// answer counts as gone, so a flaky request never tears a session down.
+async fn thread_exists(ctx: &Context, channel: ChannelId) -> bool {
+    match ctx.http().get_channel(channel).await {
+        Err(serenity::Error::Http(HttpError::UnsuccessfulRequest(res))) => {
+            res.error.code != UNKNOWN_CHANNEL
         }
+        _ => true,
+    }
+}
 
-        let mut redis_connection = redis_connection?;
-
-        println!("Interaction received: {:?}", command);
-        println!("from : {:?}", command.user.global_name.clone().unwrap());
-
-        match command.data.name.as_str() {
-            "pinga" => Some(commands::ping::run(&command.data.options())),
-            "cancel" => {
-                let res = cancel_wait(&ctx, &command, &mut redis_connection).await?;
-                command
-                    .create_response(
-                        ctx.http,
-                        CreateInteractionResponse::Message(
-                            CreateInteractionResponseMessage::new()
-                                .content(res)
-                                .ephemeral(true),
-                        ),
-                    )
-                    .await?;
-                return Ok(());
+/// Brings persisted sessions back in line with Discord after a restart: live
+/// pairs are resumed, users whose thread vanished are dropped, their partners
+/// go back in the queue, and everyone waiting gets a fresh matching attempt.
+async fn reconcile(ctx: &Context) -> Result<(), GenericError> {
+    let sessions = get_sessions(ctx).await;
+    let mut alive = HashMap::new();
+    for (id, user) in sessions.all_users().await? {
+        match user {
+            Some(user) if thread_exists(ctx, user.channel).await => {
+                alive.insert(id, user);
             }
-            "start" => {
-                println!(
-                    "Interaction received: {:?}",
-                    command.user.global_name.clone().unwrap()
-                );
-                command.defer_ephemeral(&ctx.http).await?;
-                command
-                    .edit_response(
-                        &ctx.http,
-                        EditInteractionResponse::new().content("Starting Connection"),
-                    )
-                    .await?;
-
-                let response = matcher(&ctx, &command, &mut redis_connection).await;
-
-                match response {
-                    Ok(msg) => {
-                        let x = command
-                            .edit_response(
-                                &ctx.http,
-                                EditInteractionResponse::new().content(msg).button(
-                                    CreateButton::new("cancel")
-                                        .style(ButtonStyle::Danger)
-                                        .label("Cancel"),
-                                ),
-                            )
-                            .await?;
-
-                        let mut interaction_stream = x.await_component_interaction(&ctx).stream();
-
-                        while let Some(interaction) = interaction_stream.next().await {
-                            match interaction.data.custom_id.as_str() {
-                                "cancel" => {
-                                    cancel_wait(&ctx, &command, &mut redis_connection).await?;
-                                    command
-                                        .edit_response(
-                                            &ctx.http,
-                                            EditInteractionResponse::new().content("Cancelled"),
-                                        )
-                                        .await?;
-                                    interaction
-                                        .create_response(
-                                            &ctx.http,
-                                            CreateInteractionResponse::UpdateMessage(
-                                                CreateInteractionResponseMessage::new()
-                                                    .content("Successfully cancelled the request"),
-                                            ),
-                                        )
-                                        .await?;
-                                    interaction.delete_response(&ctx.http).await?;
-
-                                    break;
-                                }
-                                _ => {
-                                    command
-                                        .edit_response(
-                                            &ctx.http,
-                                            EditInteractionResponse::new()
-                                                .content("Unknown command"),
-                                        )
-                                        .await?;
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        println!("Error: {:?}", e);
-                        command
-                            .edit_response(
-                                &ctx.http,
-                                EditInteractionResponse::new().content("Some error occured"),
-                            )
-                            .await?;
-                    }
-                }
-                Some("Ok".to_string())
+            user => {
+                println!("Dropping session for {id}, thread is gone");
+                sessions.forget(id, user.map(|u| u.channel)).await?;
             }
-            "leave" => {
-                disconnect_users(command.user.id, &ctx, &mut redis_connection).await?;
+        }
+    }
 
-                Some("Ok".to_string())
-            }
-            _ => Some("Unknown command".to_string()),
+    let mut waiting = vec![];
+    for user in alive.values() {
+        let Some(partner) = user.partner else {
+            waiting.push(user.id);
+            continue;
         };
+        let resumed = alive
+            .get(&partner)
+            .is_some_and(|p| p.partner == Some(user.id));
+        if resumed {
+            user.channel
+                .say(&ctx.http, "The bot restarted, you are still connected")
+                .await?;
+        } else if sessions
+            .requeue(
+                &User {
+                    last_active: now_secs(),
+                    ..user.clone()
+                },
+                partner,
+                now_millis(),
+            )
+            .await?
+        {
+            user.channel
+                .say(
+                    &ctx.http,
+                    "Your partner left while the bot was offline, waiting for user to connect",
+                )
+                .await?;
+            waiting.push(user.id);
+        }
     }
-    return Ok(());
-}
 
-async fn redis_delete(
-    key: &str,
-    redis_connection: &mut redis::Connection,
-) -> Result<(), GenericError> {
-    let _: () = redis_connection.del(key)?;
+    let now = now_secs();
+    for user_id in waiting {
+        if try_match(ctx, user_id, &*sessions).await? {
+            continue;
+        }
+        // The expiry task from before the restart is gone, start a new one.
+        if let Some(wait_until) = alive.get(&user_id).map(|u| u.wait_until) {
+            if wait_until > now {
+                schedule_expiry(ctx, user_id, wait_until - now);
+            }
+        }
+    }
+    println!("Reconciled {} sessions", alive.len());
     Ok(())
 }
 
-async fn redis_set(
-    key: &str,
-    value: &str,
-    redis_connection: &mut redis::Connection,
+struct Sessions;
+
+impl TypeMapKey for Sessions {
+    type Value = Arc<dyn SessionStore>;
+}
+
+/// Hands out the shared session store. For Redis this multiplexes over one
+/// connection that reconnects in the background after a failure.
+async fn get_sessions(ctx: &Context) -> Arc<dyn SessionStore> {
+    let data = ctx.data.read().await;
+    data.get::<Sessions>()
+        .cloned()
+        .expect("Sessions is inserted before the client starts")
+}
+
+async fn try_interaction_create(
+    ctx: Context,
+    interaction: Interaction,
 ) -> Result<(), GenericError> {
-    let _: () = redis_connection.set(key, value)?;
-    Ok(())
+    match interaction {
+        Interaction::Command(command) => {
+            println!("Interaction received: {:?}", command);
+            println!("from : {:?}", command.user.name);
+            commands::dispatch(&ctx, &command).await
+        }
+        Interaction::Component(component) => components::dispatch(&ctx, &component).await,
+        _ => Ok(()),
+    }
 }
+
 #[async_trait]
 impl EventHandler for Handler {
     // async fn channel_delete(
@@ -435,77 +809,71 @@ impl EventHandler for Handler {
         _channel: Option<GuildChannel>,
     ) {
         println!("Thread deleted: {:?}", partial_channel.id);
-        let mut redis_connection = get_redis_connection().unwrap();
-        let connected: String = redis_connection.get("connected").unwrap();
-        let connecting: String = redis_connection.get("connecting").unwrap();
-
-        let mut connecting_vec: Vec<User> = serde_json::from_str(&connecting).unwrap();
+        let sessions = get_sessions(&ctx).await;
+        if let Err(e) = end_session_for_channel(&ctx, partial_channel.id, &*sessions).await {
+            let context = ErrorContext::new("thread_delete").channel(partial_channel.id);
+            report_error(&ctx, &context, &e).await;
+        }
+    }
 
-        let mut connected_vec: Vec<User> = serde_json::from_str(&connected).unwrap();
-        let user = if let Some(user) = connected_vec
-            .iter()
-            .find(|u| u.channel == partial_channel.id)
-        {
-            user.clone()
-        } else {
-            if let Some(user) = connecting_vec
-                .clone()
-                .iter()
-                .find(|u| u.channel == partial_channel.id)
-            {
-                connecting_vec.retain(|u| u.channel != user.channel);
-                let connecting_ser = serde_json::to_string(&connecting_vec).unwrap();
-                redis_set("connecting", &connecting_ser, &mut redis_connection)
-                    .await
-                    .unwrap();
-                return;
-            } else {
-                return;
-            };
-        };
-        let thread_id = user.channel;
-        let partner_thread_id = user.partner_channel.unwrap();
-
-        let res = ctx
-            .http()
-            .delete_channel(partner_thread_id, Some("Partner Left the chat"))
-            .await;
-        match res {
-            Ok(_) => {
-                println!("Thread deleted successfully");
-            }
-            Err(_) => {
-                // println!("Error deleting thread: {:?}", e);
-                return;
-            }
+    async fn thread_update(&self, ctx: Context, _old: Option<GuildChannel>, new: GuildChannel) {
+        if !new.thread_metadata.is_some_and(|m| m.archived) {
+            return;
         }
-        redis_delete(&user.id.to_string(), &mut redis_connection)
-            .await
-            .unwrap();
-        redis_delete(&user.partner.unwrap().to_string(), &mut redis_connection)
-            .await
-            .unwrap();
-        redis_delete(&thread_id.to_string(), &mut redis_connection)
-            .await
-            .unwrap();
-        redis_delete(&partner_thread_id.to_string(), &mut redis_connection)
-            .await
-            .unwrap();
+        // Threads the bot closes itself have no session left by then, so this
+        // only acts on auto-archival or a moderator archiving a live chat.
+        let sessions = get_sessions(&ctx).await;
+        if let Err(e) = end_session_for_channel(&ctx, new.id, &*sessions).await {
+            let context = ErrorContext::new("thread_update").channel(new.id);
+            report_error(&ctx, &context, &e).await;
+        }
+    }
 
-        connected_vec.retain(|u| u.channel != partner_thread_id);
-        connected_vec.retain(|u| u.channel != thread_id);
+    async fn guild_delete(&self, ctx: Context, incomplete: UnavailableGuild, _full: Option<Guild>) {
+        // An outage also arrives as a guild delete; those sessions resume
+        // when the guild comes back.
+        if incomplete.unavailable {
+            println!("Guild unavailable: {}", incomplete.id);
+            return;
+        }
+        println!("Removed from guild: {}", incomplete.id);
+        let sessions = get_sessions(&ctx).await;
+        if let Err(e) = end_sessions_in_guild(&ctx, incomplete.id, &*sessions).await {
+            report_error(&ctx, &ErrorContext::new("guild_delete"), &e).await;
+        }
+    }
 
-        let connected_ser = serde_json::to_string(&connected_vec).unwrap();
-        redis_set("connected", &connected_ser, &mut redis_connection)
-            .await
-            .unwrap();
+    async fn guild_member_removal(
+        &self,
+        ctx: Context,
+        guild_id: GuildId,
+        user: serenity::all::User,
+        _member: Option<Member>,
+    ) {
+        let sessions = get_sessions(&ctx).await;
+        let result = async {
+            match sessions.load_user(user.id).await? {
+                Some(session) if session.guild == Some(guild_id) => {
+                    end_session(&ctx, &session, &*sessions).await?;
+                    close_thread(&ctx, session.channel).await
+                }
+                _ => Ok(()),
+            }
+        };
+        if let Err(e) = result.await {
+            let context = ErrorContext::new("guild_member_removal").user(user.id);
+            report_error(&ctx, &context, &e).await;
+        }
     }
 
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        let _res = try_interaction_create(ctx, interaction).await;
+        let context = ErrorContext::interaction(&interaction);
+        if let Err(e) = try_interaction_create(ctx.clone(), interaction).await {
+            report_error(&ctx, &context, &e).await;
+        }
     }
     async fn guild_create(&self, _ctx: Context, guild: Guild, is_new: Option<bool>) {
-        if is_new.unwrap() {
+        if is_new == Some(true) {
             println!("Bot joined a new guild:");
             println!("Guild Name: {}", guild.name);
             println!("Guild ID: {}", guild.id);
@@ -513,77 +881,73 @@ impl EventHandler for Handler {
         }
     }
     async fn message(&self, ctx: Context, msg: Message) {
-        let is_bot = msg.author.bot;
-        if is_bot {
-            return;
+        if let Err(e) = relay::relay_message(&ctx, &msg).await {
+            let context = ErrorContext::new("message")
+                .user(msg.author.id)
+                .channel(msg.channel_id);
+            report_error(&ctx, &context, &e).await;
         }
-        let thread_name = format!(
-            "{}{}",
-            msg.author.id.to_string(),
-            msg.author.global_name.clone().unwrap()
-        );
-        let name = msg.channel_id.name(&ctx.http).await.unwrap();
+    }
 
-        if thread_name != name {
-            return;
+    async fn message_update(
+        &self,
+        ctx: Context,
+        _old_if_available: Option<Message>,
+        _new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        if let Err(e) = relay::relay_edit(&ctx, &event).await {
+            let context = ErrorContext::new("message_update").channel(event.channel_id);
+            report_error(&ctx, &context, &e).await;
         }
+    }
 
-        let chan_id = msg.channel_id;
-
-        let atch = &msg.attachments;
-        if atch.len() > 0 {
-            msg.delete(&ctx.http).await.unwrap();
-            chan_id
-                .say(
-                    &ctx.http,
-                    "Attachments are not allowed\n Premium comming soon!~",
-                )
-                .await
-                .unwrap();
-            return;
+    async fn message_delete(
+        &self,
+        ctx: Context,
+        channel_id: ChannelId,
+        deleted_message_id: MessageId,
+        _guild_id: Option<GuildId>,
+    ) {
+        if let Err(e) = relay::relay_delete(&ctx, channel_id, deleted_message_id).await {
+            let context = ErrorContext::new("message_delete").channel(channel_id);
+            report_error(&ctx, &context, &e).await;
         }
-        let stckr = &msg.sticker_items;
-        if stckr.len() > 0 {
-            msg.delete(&ctx.http).await.unwrap();
-            chan_id
-                .say(
-                    &ctx.http,
-                    "Stickers are not allowed\n Premium comming soon!~",
-                )
-                .await
-                .unwrap();
-            return;
+    }
+
+    async fn message_delete_bulk(
+        &self,
+        ctx: Context,
+        channel_id: ChannelId,
+        multiple_deleted_messages_ids: Vec<MessageId>,
+        _guild_id: Option<GuildId>,
+    ) {
+        for id in multiple_deleted_messages_ids {
+            if let Err(e) = relay::relay_delete(&ctx, channel_id, id).await {
+                let context = ErrorContext::new("message_delete_bulk").channel(channel_id);
+                report_error(&ctx, &context, &e).await;
+            }
         }
+    }
 
-        let cha = msg.channel(&ctx.http).await.unwrap();
-        let kind = cha.guild().unwrap().kind;
-        match kind {
-            ChannelType::PrivateThread => {
-                println!("Thread name:  {}", thread_name);
+    async fn reaction_add(&self, ctx: Context, add_reaction: Reaction) {
+        if let Err(e) = relay::relay_reaction(&ctx, &add_reaction, true).await {
+            let context = ErrorContext::new("reaction_add").channel(add_reaction.channel_id);
+            report_error(&ctx, &context, &e).await;
+        }
+    }
 
-                let target_chan: Result<String, redis::RedisError> =
-                    get_redis_connection().unwrap().get(chan_id.to_string());
+    async fn reaction_remove(&self, ctx: Context, removed_reaction: Reaction) {
+        if let Err(e) = relay::relay_reaction(&ctx, &removed_reaction, false).await {
+            let context = ErrorContext::new("reaction_remove").channel(removed_reaction.channel_id);
+            report_error(&ctx, &context, &e).await;
+        }
+    }
 
-                match target_chan {
-                    Ok(target_chan) => {
-                        println!("Target channel: {:?}", target_chan);
-                        let target_chan_id = ChannelId::from(target_chan.parse::<u64>().unwrap());
-                        target_chan_id.say(&ctx.http, msg.content).await.unwrap();
-                    }
-                    Err(e) => {
-                        println!("Error: {:?}", e);
-                        chan_id
-                            .say(
-                                &ctx.http,
-                                "You are not connected to anyone Please wait until someone connect",
-                            )
-                            .await
-                            .unwrap();
-                        msg.delete(&ctx.http).await.unwrap();
-                    }
-                };
-            }
-            _ => {}
+    async fn typing_start(&self, ctx: Context, event: TypingStartEvent) {
+        if let Err(e) = relay::relay_typing(&ctx, &event).await {
+            let context = ErrorContext::new("typing_start").channel(event.channel_id);
+            report_error(&ctx, &context, &e).await;
         }
     }
 
@@ -593,30 +957,16 @@ impl EventHandler for Handler {
         println!("{cache:#?}");
 
         ctx.set_activity(Some(ActivityData::playing("Bot-Bot")));
-        // for command in Command::get_global_commands(&ctx.http)
-        //     .await
-        //     .unwrap()
-        //     .iter()
-        // {
-        //     println!("Command: {:?}", command.id);
-        //     Command::delete_global_command(&ctx.http, command.id)
-        //         .await
-        //         .unwrap();
-        // }
-
-        // let _ = Command::create_global_command(&ctx.http, commands::ping::register()).await;
-
-        Command::create_global_command(&ctx.http, commands::start::register())
-            .await
-            .unwrap();
-
-        Command::create_global_command(&ctx.http, commands::leave::register())
-            .await
-            .unwrap();
 
-        Command::create_global_command(&ctx.http, commands::cancel::register())
-            .await
-            .unwrap();
+        if !self.reconciled.swap(true, Ordering::SeqCst) {
+            if let Err(e) = reconcile(&ctx).await {
+                report_error(&ctx, &ErrorContext::new("startup reconciliation"), &e).await;
+            }
+            scheduler::spawn(&ctx);
+        }
+        if let Err(e) = commands::register_all(&ctx).await {
+            report_error(&ctx, &ErrorContext::new("command registration"), &e).await;
+        }
 
         // println!("I created the following global slash command: {c1:#?} {c2:#?} {c3:#?}");
         // println!("I created the following global slash command: {guild_command2:#?}");
@@ -625,37 +975,29 @@ impl EventHandler for Handler {
 
 #[tokio::main]
 async fn main() {
-    let mut redis_connection = get_redis_connection();
-    match &redis_connection {
-        Ok(_con) => {
-            println!("Connected to redis");
+    let sessions: Arc<dyn SessionStore> = match std::env::var("REDIS_URL") {
+        Ok(url) => {
+            let redis_client = redis::Client::open(url);
+            let redis_connection = match redis_client {
+                Ok(client) => ConnectionManager::new(client).await,
+                Err(e) => Err(e),
+            };
+            match redis_connection {
+                Ok(con) => {
+                    println!("Connected to redis");
+                    Arc::new(RedisStore::new(con))
+                }
+                Err(e) => {
+                    println!("Error connecting to redis : {:?}", e);
+                    return;
+                }
+            }
         }
-        Err(e) => {
-            println!("Error connecting to redis : {:?}", e);
-            return;
+        Err(_) => {
+            println!("REDIS_URL not set, keeping sessions in memory (single-process dev mode)");
+            Arc::new(MemoryStore::new())
         }
-    }
-    let _: String = redis::cmd("FLUSHALL")
-        .query(&mut redis_connection.as_mut().unwrap())
-        .unwrap();
-    let connecting_vec: Vec<u64> = vec![];
-    let connected_vec: Vec<u64> = vec![];
-
-    let connecting_ser = serde_json::to_string(&connecting_vec).unwrap();
-    let connected_ser = serde_json::to_string(&connected_vec).unwrap();
-    let _: Result<String, redis::RedisError> = redis_connection
-        .as_mut()
-        .unwrap()
-        .set("connecting", connecting_ser);
-
-    let _: Result<String, redis::RedisError> = redis_connection
-        .as_mut()
-        .unwrap()
-        .set("connected", connected_ser);
-
-    let _: Result<String, redis::RedisError> = redis_connection.as_mut().unwrap().set("con", "1");
-
-    drop(redis_connection);
+    };
 
     // Configure the client with your Discord bot token in the environment.
     let token = std::env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
@@ -669,7 +1011,11 @@ async fn main() {
     // Create a new instance of the Client, logging in as a bot. This will automatically prepend
     // your bot token with "Bot ", which is a requirement by Discord for bot users.
     let mut client = Client::builder(&token, intents)
-        .event_handler(Handler)
+        .event_handler(Handler {
+            reconciled: AtomicBool::new(false),
+        })
+        .type_map_insert::<Sessions>(sessions)
+        .type_map_insert::<Config>(Arc::new(Config::from_env()))
         .await
         .expect("Err creating client");
 
@@ -681,3 +1027,158 @@ async fn main() {
         println!("Client error: {why:?}");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn waits_for_shared_interest_inside_window() {
+        let sessions = MemoryStore::new();
+        for (id, interest) in [(1, "chess"), (2, "rust")] {
+            let user = User::test(id)
+                .with_interests(&[interest])
+                .with_wait_until(100);
+            sessions.enqueue(&user, id).await.unwrap();
+        }
+
+        let outcome = claim_match(&sessions, UserId::new(2), 50).await.unwrap();
+        assert!(matches!(outcome, MatchOutcome::Waiting));
+
+        // Once both windows are over they settle for each other.
+        let outcome = claim_match(&sessions, UserId::new(2), 100).await.unwrap();
+        assert!(matches!(outcome, MatchOutcome::Paired(..)));
+    }
+
+    #[tokio::test]
+    async fn never_pairs_users_who_blocked_each_other() {
+        let sessions = MemoryStore::new();
+        for id in 1..=3 {
+            sessions.enqueue(&User::test(id), id).await.unwrap();
+        }
+        // 1 blocked 2, and 3 blocked 1.
+        sessions
+            .block(UserId::new(1), UserId::new(2))
+            .await
+            .unwrap();
+        sessions
+            .block(UserId::new(3), UserId::new(1))
+            .await
+            .unwrap();
+
+        let outcome = claim_match(&sessions, UserId::new(1), 0).await.unwrap();
+        assert!(matches!(outcome, MatchOutcome::Waiting));
+        match claim_match(&sessions, UserId::new(2), 0).await.unwrap() {
+            MatchOutcome::Paired(a, b) => {
+                assert_eq!((a.id, b.id), (UserId::new(2), UserId::new(3)))
+            }
+            outcome => panic!("expected a pairing, got {outcome:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn never_pairs_users_into_a_server_that_banned_them() {
+        use bans::{Ban, BanScope};
+
+        let sessions = MemoryStore::new();
+        // 1 is banned in server 10, where 2 chats from; 3 chats from 20.
+        sessions
+            .add_ban(&Ban {
+                user: UserId::new(1),
+                scope: BanScope::Guild(GuildId::new(10)),
+                reason: None,
+                banned_by: UserId::new(9),
+                created_at: 0,
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+        for (id, guild) in [(1, 20), (2, 10)] {
+            let user = User::test(id).with_guild(guild);
+            sessions.enqueue(&user, id).await.unwrap();
+        }
+
+        // Neither side may pick the other.
+        for id in [1, 2] {
+            let outcome = claim_match(&sessions, UserId::new(id), 0).await.unwrap();
+            assert!(matches!(outcome, MatchOutcome::Waiting), "{id}");
+        }
+        sessions
+            .enqueue(&User::test(3).with_guild(20), 3)
+            .await
+            .unwrap();
+        match claim_match(&sessions, UserId::new(2), 0).await.unwrap() {
+            MatchOutcome::Paired(_, b) => assert_eq!(b.id, UserId::new(3)),
+            outcome => panic!("expected a pairing, got {outcome:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn prefers_the_most_shared_interests() {
+        let sessions = MemoryStore::new();
+        let interests: [&[&str]; 3] = [&["rust"], &["rust", "chess"], &["rust", "chess", "go"]];
+        for (id, interests) in (1..).zip(interests) {
+            let user = User::test(id)
+                .with_interests(interests)
+                .with_wait_until(100);
+            sessions.enqueue(&user, id).await.unwrap();
+        }
+
+        match claim_match(&sessions, UserId::new(3), 50).await.unwrap() {
+            MatchOutcome::Paired(a, b) => {
+                assert_eq!(a.id, UserId::new(3));
+                assert_eq!(b.id, UserId::new(2));
+                assert_eq!(
+                    shared_interests(&a.interests, &b.interests),
+                    vec!["rust", "chess"]
+                );
+            }
+            outcome => panic!("expected a pairing, got {outcome:?}"),
+        }
+        let outcome = claim_match(&sessions, UserId::new(2), 50).await.unwrap();
+        assert!(matches!(outcome, MatchOutcome::AlreadyPaired));
+    }
+
+    #[tokio::test]
+    async fn disconnect_ends_the_session_once() {
+        let sessions = MemoryStore::new();
+        sessions.enqueue(&User::test(1), 1).await.unwrap();
+        sessions.enqueue(&User::test(2), 2).await.unwrap();
+        claim_match(&sessions, UserId::new(2), 0).await.unwrap();
+
+        let ended = end_pairing(&sessions, UserId::new(2)).await.unwrap();
+        let (u, partner) = ended.expect("session should end");
+        assert_eq!((u.id.get(), partner.id.get()), (2, 1));
+        assert!(end_pairing(&sessions, UserId::new(1))
+            .await
+            .unwrap()
+            .is_none());
+        assert!(sessions.all_users().await.unwrap().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_matching_never_double_pairs() {
+        let sessions = Arc::new(MemoryStore::new());
+        for id in 1..=40 {
+            sessions.enqueue(&User::test(id), id).await.unwrap();
+        }
+        let tasks: Vec<_> = (1..=40)
+            .map(|id| {
+                let sessions = sessions.clone();
+                tokio::spawn(async move { claim_match(&*sessions, UserId::new(id), 0).await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        for (id, user) in sessions.all_users().await.unwrap() {
+            let Some(partner) = user.unwrap().partner else {
+                continue;
+            };
+            assert_ne!(partner, id);
+            let partner = sessions.load_user(partner).await.unwrap().unwrap();
+            assert_eq!(partner.partner, Some(id));
+        }
+    }
+}
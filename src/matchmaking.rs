@@ -0,0 +1,286 @@
+//! Pure matchmaking rules, with no Discord or storage in sight.
+//!
+//! A [`Queue`] is a snapshot of who is waiting and who is paired. Feeding it
+//! an [`Event`] yields a [`Decision`]; the bot executes that decision against
+//! the session store and Discord, and re-snapshots if the store has moved on.
+
+use std::collections::HashMap;
+
+/// What the engine needs to know about a user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub id: u64,
+    pub interests: Vec<String>,
+    /// Until this time (unix seconds) the user only accepts partners that
+    /// share an interest with them.
+    pub wait_until: u64,
+    pub anyone: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A newcomer runs /start.
+    Join(Entry),
+    /// Look again for a partner for someone already waiting, e.g. once their
+    /// interest window is over.
+    Rematch(u64),
+    /// The user gives up: waiting users leave the queue, paired users end
+    /// their session.
+    Cancel(u64),
+    /// A paired user ends their session.
+    Leave(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    AlreadyWaiting,
+    AlreadyConnected,
+    NotQueued,
+    NotConnected,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Pair(u64, u64),
+    Wait(u64),
+    Remove(u64),
+    Unpair(u64, u64),
+    Reject(u64, Rejection),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Queue {
+    /// Longest-waiting first.
+    waiting: Vec<Entry>,
+    partners: HashMap<u64, u64>,
+}
+
+pub fn shared_interests(a: &[String], b: &[String]) -> Vec<String> {
+    a.iter().filter(|i| b.contains(i)).cloned().collect()
+}
+
+/// A user accepts a partner without shared interests once they asked for
+/// `anyone` or their interest-matching window has run out.
+fn accepts_anyone(entry: &Entry, now: u64) -> bool {
+    entry.anyone || entry.wait_until <= now
+}
+
+/// Scores `b` as a partner for `a`: the number of shared interests, or `None`
+/// when either side is still holding out for an interest match.
+fn match_score(a: &Entry, b: &Entry, now: u64) -> Option<usize> {
+    if a.id == b.id {
+        return None;
+    }
+    let shared = shared_interests(&a.interests, &b.interests).len();
+    if shared > 0 || (accepts_anyone(a, now) && accepts_anyone(b, now)) {
+        Some(shared)
+    } else {
+        None
+    }
+}
+
+impl Queue {
+    pub fn new(waiting: Vec<Entry>, pairs: impl IntoIterator<Item = (u64, u64)>) -> Queue {
+        let mut partners = HashMap::new();
+        for (a, b) in pairs {
+            partners.insert(a, b);
+            partners.insert(b, a);
+        }
+        Queue { waiting, partners }
+    }
+
+    pub fn partner(&self, id: u64) -> Option<u64> {
+        self.partners.get(&id).copied()
+    }
+
+    fn waiting_entry(&self, id: u64) -> Option<&Entry> {
+        self.waiting.iter().find(|e| e.id == id)
+    }
+
+    /// Picks the waiting user sharing the most interests with `entry`,
+    /// preferring whoever has been in the queue longest on ties.
+    pub fn best_match(&self, entry: &Entry, now: u64) -> Option<&Entry> {
+        let mut best: Option<(&Entry, usize)> = None;
+        for candidate in &self.waiting {
+            if let Some(score) = match_score(entry, candidate, now) {
+                if best.is_none_or(|(_, best_score)| score > best_score) {
+                    best = Some((candidate, score));
+                }
+            }
+        }
+        best.map(|(candidate, _)| candidate)
+    }
+
+    pub fn decide(&self, event: &Event, now: u64) -> Decision {
+        match event {
+            Event::Join(entry) => {
+                if self.partners.contains_key(&entry.id) {
+                    Decision::Reject(entry.id, Rejection::AlreadyConnected)
+                } else if self.waiting_entry(entry.id).is_some() {
+                    Decision::Reject(entry.id, Rejection::AlreadyWaiting)
+                } else {
+                    self.pair_or_wait(entry, now)
+                }
+            }
+            Event::Rematch(id) => match self.waiting_entry(*id) {
+                Some(entry) => self.pair_or_wait(entry, now),
+                None if self.partners.contains_key(id) => {
+                    Decision::Reject(*id, Rejection::AlreadyConnected)
+                }
+                None => Decision::Reject(*id, Rejection::NotQueued),
+            },
+            Event::Cancel(id) => match self.partner(*id) {
+                Some(partner) => Decision::Unpair(*id, partner),
+                None if self.waiting_entry(*id).is_some() => Decision::Remove(*id),
+                None => Decision::Reject(*id, Rejection::NotQueued),
+            },
+            Event::Leave(id) => match self.partner(*id) {
+                Some(partner) => Decision::Unpair(*id, partner),
+                None => Decision::Reject(*id, Rejection::NotConnected),
+            },
+        }
+    }
+
+    fn pair_or_wait(&self, entry: &Entry, now: u64) -> Decision {
+        match self.best_match(entry, now) {
+            Some(partner) => Decision::Pair(entry.id, partner.id),
+            None => Decision::Wait(entry.id),
+        }
+    }
+
+    /// Decides on `event` and applies the outcome to this snapshot.
+    #[cfg(test)]
+    pub fn step(&mut self, event: Event, now: u64) -> Decision {
+        let decision = self.decide(&event, now);
+        match decision {
+            Decision::Pair(a, b) => {
+                self.waiting.retain(|e| e.id != a && e.id != b);
+                self.partners.insert(a, b);
+                self.partners.insert(b, a);
+            }
+            Decision::Wait(id) => {
+                if let Event::Join(entry) = event {
+                    if entry.id == id {
+                        self.waiting.push(entry);
+                    }
+                }
+            }
+            Decision::Remove(id) => self.waiting.retain(|e| e.id != id),
+            Decision::Unpair(a, b) => {
+                self.partners.remove(&a);
+                self.partners.remove(&b);
+            }
+            Decision::Reject(..) => {}
+        }
+        decision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const INTERESTS: [&str; 4] = ["rust", "chess", "music", "go"];
+
+    fn entry(id: u64, interests: &[&str], wait_until: u64) -> Entry {
+        Entry {
+            id,
+            interests: interests.iter().map(|i| i.to_string()).collect(),
+            wait_until,
+            anyone: false,
+        }
+    }
+
+    #[test]
+    fn join_pairs_on_shared_interest() {
+        let queue = Queue::new(vec![entry(1, &["go"], 10), entry(2, &["rust"], 10)], []);
+        let decision = queue.decide(&Event::Join(entry(3, &["rust"], 10)), 0);
+        assert_eq!(decision, Decision::Pair(3, 2));
+    }
+
+    #[test]
+    fn join_waits_inside_window_without_overlap() {
+        let queue = Queue::new(vec![entry(1, &["go"], 0)], []);
+        let decision = queue.decide(&Event::Join(entry(2, &["rust"], 10)), 0);
+        assert_eq!(decision, Decision::Wait(2));
+        // Asking for anyone skips the window.
+        let mut anyone = entry(2, &["rust"], 10);
+        anyone.anyone = true;
+        assert_eq!(queue.decide(&Event::Join(anyone), 0), Decision::Pair(2, 1));
+    }
+
+    #[test]
+    fn rejects_known_users() {
+        let queue = Queue::new(vec![entry(1, &[], 0)], [(2, 3)]);
+        assert_eq!(
+            queue.decide(&Event::Join(entry(1, &[], 0)), 0),
+            Decision::Reject(1, Rejection::AlreadyWaiting)
+        );
+        assert_eq!(
+            queue.decide(&Event::Join(entry(2, &[], 0)), 0),
+            Decision::Reject(2, Rejection::AlreadyConnected)
+        );
+        assert_eq!(
+            queue.decide(&Event::Leave(1), 0),
+            Decision::Reject(1, Rejection::NotConnected)
+        );
+        assert_eq!(
+            queue.decide(&Event::Cancel(4), 0),
+            Decision::Reject(4, Rejection::NotQueued)
+        );
+        assert_eq!(queue.decide(&Event::Cancel(3), 0), Decision::Unpair(3, 2));
+    }
+
+    fn arb_event() -> impl Strategy<Value = Event> {
+        let id = 1u64..8;
+        prop_oneof![
+            (
+                id.clone(),
+                proptest::sample::subsequence(INTERESTS.to_vec(), 0..3),
+                0u64..20,
+                any::<bool>()
+            )
+                .prop_map(|(id, interests, wait_until, anyone)| {
+                    Event::Join(Entry {
+                        id,
+                        interests: interests.iter().map(|i| i.to_string()).collect(),
+                        wait_until,
+                        anyone,
+                    })
+                }),
+            id.clone().prop_map(Event::Rematch),
+            id.clone().prop_map(Event::Cancel),
+            id.prop_map(Event::Leave),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn nobody_is_paired_twice_or_with_themselves(
+            events in proptest::collection::vec((arb_event(), 0u64..3), 0..60)
+        ) {
+            let mut queue = Queue::default();
+            let mut now = 0;
+            for (event, tick) in events {
+                now += tick;
+                let before = queue.clone();
+                if let Decision::Pair(a, b) = queue.step(event, now) {
+                    prop_assert_ne!(a, b);
+                    prop_assert!(before.partner(a).is_none());
+                    prop_assert!(before.partner(b).is_none());
+                }
+
+                for (a, b) in &queue.partners {
+                    prop_assert_ne!(a, b);
+                    prop_assert_eq!(queue.partner(*b), Some(*a));
+                    prop_assert!(queue.waiting_entry(*a).is_none());
+                }
+                let mut ids: Vec<u64> = queue.waiting.iter().map(|e| e.id).collect();
+                ids.sort();
+                ids.dedup();
+                prop_assert_eq!(ids.len(), queue.waiting.len());
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Which files may cross between strangers. Every server has a
+//! [`MediaPolicy`], falling back to the bot-wide one, and files only flow
+//! between two users who both turned on `/media`.
+
+/// Fits Discord's upload limit in servers without boosts.
+pub const DEFAULT_MAX_BYTES: u32 = 8 * 1024 * 1024;
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaPolicy {
+    /// Whether files and stickers are relayed at all.
+    pub enabled: bool,
+    pub max_bytes: u32,
+    pub image_only: bool,
+    /// MIME types (`image/png`, `video/*`) or file extensions (`pdf`). Empty
+    /// allows every type.
+    pub allowed_types: Vec<String>,
+}
+
+impl Default for MediaPolicy {
+    fn default() -> MediaPolicy {
+        MediaPolicy {
+            enabled: false,
+            max_bytes: DEFAULT_MAX_BYTES,
+            image_only: false,
+            allowed_types: vec![],
+        }
+    }
+}
+
+/// Why a file was not relayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Refusal {
+    Disabled,
+    NotOptedIn,
+    TooLarge(u32),
+    NotAnImage,
+    TypeNotAllowed,
+}
+
+impl std::fmt::Display for Refusal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Refusal::Disabled => write!(f, "files are turned off on this server"),
+            Refusal::NotOptedIn => {
+                write!(f, "you and your partner both need to turn on /media")
+            }
+            Refusal::TooLarge(limit) => {
+                write!(f, "larger than the {} limit", describe_size(*limit))
+            }
+            Refusal::NotAnImage => write!(f, "only images are allowed"),
+            Refusal::TypeNotAllowed => write!(f, "this file type is not allowed"),
+        }
+    }
+}
+
+impl MediaPolicy {
+    /// Whether a file may be relayed under this policy.
+    pub fn check(
+        &self,
+        filename: &str,
+        content_type: Option<&str>,
+        size: u32,
+    ) -> Result<(), Refusal> {
+        if !self.enabled {
+            return Err(Refusal::Disabled);
+        }
+        if size > self.max_bytes {
+            return Err(Refusal::TooLarge(self.max_bytes));
+        }
+        let extension = filename
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_ascii_lowercase())
+            .unwrap_or_default();
+        // Drop parameters such as "; charset=utf-8".
+        let mime = content_type.map(|m| {
+            m.split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase()
+        });
+
+        let is_image = match &mime {
+            Some(mime) => mime.starts_with("image/"),
+            None => IMAGE_EXTENSIONS.contains(&extension.as_str()),
+        };
+        if self.image_only && !is_image {
+            return Err(Refusal::NotAnImage);
+        }
+        let allowed = self.allowed_types.is_empty()
+            || self
+                .allowed_types
+                .iter()
+                .any(|t| type_matches(t, mime.as_deref(), &extension));
+        if !allowed {
+            return Err(Refusal::TypeNotAllowed);
+        }
+        Ok(())
+    }
+
+    /// One line per setting, for showing the policy to an admin.
+    pub fn describe(&self) -> String {
+        let types = if self.allowed_types.is_empty() {
+            "any".to_string()
+        } else {
+            self.allowed_types.join(", ")
+        };
+        format!(
+            "Enabled: {}\nSize limit: {}\nImages only: {}\nAllowed types: {types}",
+            self.enabled,
+            describe_size(self.max_bytes),
+            self.image_only
+        )
+    }
+}
+
+/// A comma separated list of MIME types and extensions, lowercased.
+pub fn parse_types(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|t| t.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+fn type_matches(allowed: &str, mime: Option<&str>, extension: &str) -> bool {
+    if !allowed.contains('/') {
+        return allowed == extension;
+    }
+    let Some(mime) = mime else {
+        return false;
+    };
+    match allowed.strip_suffix('*') {
+        Some(prefix) => mime.starts_with(prefix),
+        None => mime == allowed,
+    }
+}
+
+fn describe_size(bytes: u32) -> String {
+    const MB: u32 = 1024 * 1024;
+    if bytes >= MB {
+        format!("{} MB", bytes / MB)
+    } else {
+        format!("{} KB", bytes / 1024)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled() -> MediaPolicy {
+        MediaPolicy {
+            enabled: true,
+            ..MediaPolicy::default()
+        }
+    }
+
+    #[test]
+    fn disabled_and_oversized_files_are_refused() {
+        let policy = MediaPolicy::default();
+        assert_eq!(policy.check("a.png", None, 1), Err(Refusal::Disabled));
+
+        let policy = MediaPolicy {
+            max_bytes: 1024,
+            ..enabled()
+        };
+        assert_eq!(policy.check("a.png", None, 1024), Ok(()));
+        assert_eq!(
+            policy.check("a.png", None, 1025),
+            Err(Refusal::TooLarge(1024))
+        );
+    }
+
+    #[test]
+    fn image_only_goes_by_mime_type_first() {
+        let policy = MediaPolicy {
+            image_only: true,
+            ..enabled()
+        };
+        assert_eq!(policy.check("a.JPG", None, 1), Ok(()));
+        assert_eq!(policy.check("a", Some("image/webp"), 1), Ok(()));
+        assert_eq!(
+            policy.check("a.png", Some("application/octet-stream"), 1),
+            Err(Refusal::NotAnImage)
+        );
+        assert_eq!(policy.check("a.pdf", None, 1), Err(Refusal::NotAnImage));
+    }
+
+    #[test]
+    fn allowlist_matches_mime_types_wildcards_and_extensions() {
+        let policy = MediaPolicy {
+            allowed_types: parse_types("video/*, audio/ogg, .PDF"),
+            ..enabled()
+        };
+        assert_eq!(policy.allowed_types, ["video/*", "audio/ogg", "pdf"]);
+        assert_eq!(policy.check("a.mp4", Some("video/mp4"), 1), Ok(()));
+        assert_eq!(
+            policy.check("a.ogg", Some("audio/ogg; codecs=opus"), 1),
+            Ok(())
+        );
+        assert_eq!(policy.check("doc.pdf", None, 1), Ok(()));
+        assert_eq!(
+            policy.check("a.exe", Some("application/x-msdownload"), 1),
+            Err(Refusal::TypeNotAllowed)
+        );
+        assert_eq!(policy.check("a.mp4", None, 1), Err(Refusal::TypeNotAllowed));
+    }
+}
@@ -0,0 +1,94 @@
+//! The anonymous name and avatar each user is shown to their partner under.
+//! A persona is worked out from the pair's records alone, so it stays the same
+//! for the whole session, changes with every new partner and needs no storage.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use serenity::all::{ChannelId, UserId};
+
+use crate::store::User;
+
+const ANIMALS: &[(&str, &str)] = &[
+    ("🦊", "fox"),
+    ("🐼", "panda"),
+    ("🦉", "owl"),
+    ("🐙", "octopus"),
+    ("🐢", "turtle"),
+    ("🦄", "unicorn"),
+    ("🐧", "penguin"),
+    ("🦁", "lion"),
+    ("🐸", "frog"),
+    ("🐨", "koala"),
+    ("🦋", "butterfly"),
+    ("🐳", "whale"),
+];
+
+/// Generates the avatar image for a seed.
+const AVATAR_URL: &str = "https://api.dicebear.com/9.x/thumbs/png";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Persona {
+    pub name: String,
+    pub avatar_url: String,
+}
+
+impl Persona {
+    /// How `user` appears to their current partner, or `None` if they have
+    /// none. The two sides of a pair always get different animals.
+    pub fn of(user: &User) -> Option<Persona> {
+        let own = (user.id, user.channel);
+        let partner = (user.partner?, user.partner_channel?);
+        let (first, second) = if own.0 < partner.0 {
+            (own, partner)
+        } else {
+            (partner, own)
+        };
+        let seed = session_seed(first, second);
+
+        // The first side picks from every animal, the second from the rest.
+        let first_index = (seed % ANIMALS.len() as u64) as usize;
+        let index = if own == first {
+            first_index
+        } else {
+            let index = ((seed >> 32) % (ANIMALS.len() as u64 - 1)) as usize;
+            if index >= first_index {
+                index + 1
+            } else {
+                index
+            }
+        };
+        let (emoji, animal) = ANIMALS[index];
+        Some(Persona {
+            name: format!("Stranger {emoji}"),
+            avatar_url: format!("{AVATAR_URL}?seed={animal}-{seed:x}"),
+        })
+    }
+}
+
+fn session_seed(first: (UserId, ChannelId), second: (UserId, ChannelId)) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (first, second).hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partners_never_share_a_persona() {
+        for a in 1..40 {
+            for b in (a + 1)..40 {
+                let first = Persona::of(&User::test(a).with_partner(b)).unwrap();
+                let second = Persona::of(&User::test(b).with_partner(a)).unwrap();
+                assert_ne!(first.name, second.name, "{a} and {b}");
+                assert_eq!(Persona::of(&User::test(a).with_partner(b)), Some(first));
+            }
+        }
+    }
+
+    #[test]
+    fn waiting_users_have_no_persona() {
+        assert_eq!(Persona::of(&User::test(1)), None);
+    }
+}
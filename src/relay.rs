@@ -0,0 +1,626 @@
+//! Copies what the owner of a private thread does over to their partner's
+//! thread: new messages, edits, deletions, replies, reactions and typing.
+//!
+//! Messages are posted under the owner's [`Persona`] through the webhook of
+//! the partner's lobby channel, or as bot messages headed by an embed with
+//! the persona where the bot cannot use webhooks.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use serenity::all::{
+    ChannelId, Context, CreateAllowedMentions, CreateAttachment, CreateEmbed, CreateEmbedAuthor,
+    CreateMessage, EditMessage, EditWebhookMessage, ExecuteWebhook, GuildId, Message, MessageFlags,
+    MessageId, MessageReference, MessageUpdateEvent, Reaction, ReactionType, StickerId,
+    StickerItem, TypingStartEvent, UserId, Webhook,
+};
+use serenity::http::HttpError;
+
+use crate::config::get_config;
+use crate::error::GenericError;
+use crate::media::{MediaPolicy, Refusal};
+use crate::persona::Persona;
+use crate::sanitize::{sanitize, Sanitized};
+use crate::store::{MessageRef, SessionStore, User};
+use crate::strikes::{self, Strike};
+use crate::{get_sessions, now_secs, webhooks};
+
+/// Discord's "Invalid sticker sent" error code.
+const INVALID_STICKER: isize = 50081;
+
+/// Starts the line linking a webhook message to the message it replies to.
+const REPLY_PREFIX: &str = "↪ ";
+
+/// A typing indicator shows for about ten seconds, so refreshing it more
+/// often than this only spends rate limit.
+const TYPING_INTERVAL: Duration = Duration::from_secs(8);
+
+static TYPING: LazyLock<Throttle> = LazyLock::new(|| Throttle::new(TYPING_INTERVAL));
+
+/// Lets through one event per channel per `interval`, within this process.
+struct Throttle {
+    interval: Duration,
+    last: Mutex<HashMap<ChannelId, Instant>>,
+}
+
+impl Throttle {
+    fn new(interval: Duration) -> Throttle {
+        Throttle {
+            interval,
+            last: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn allow(&self, channel: ChannelId, now: Instant) -> bool {
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        last.retain(|_, at| now.duration_since(*at) < self.interval);
+        if last.contains_key(&channel) {
+            return false;
+        }
+        last.insert(channel, now);
+        true
+    }
+}
+
+/// Forwards a message from a private thread to the owner's partner.
+pub async fn relay_message(ctx: &Context, msg: &Message) -> Result<(), GenericError> {
+    let is_bot = msg.author.bot;
+    if is_bot {
+        return Ok(());
+    }
+    let chan_id = msg.channel_id;
+
+    let sessions = get_sessions(ctx).await;
+    let Some(owner) = sessions.user_by_channel(chan_id).await? else {
+        // Not one of our private threads.
+        return Ok(());
+    };
+    if owner.id != msg.author.id {
+        // Only the thread owner talks to the stranger; anyone else who can
+        // see the thread (e.g. moderators) is not relayed.
+        return Ok(());
+    }
+
+    match owner.partner_channel {
+        Some(target_chan_id) => {
+            println!("Target channel: {:?}", target_chan_id);
+            let config = get_config(ctx).await;
+            let now = now_secs();
+            let clean = sanitize(&msg.content, &config.link_policy);
+            let (Some(partner), Some(persona)) =
+                (partner_of(&*sessions, &owner).await?, Persona::of(&owner))
+            else {
+                return Err(GenericError::MissingSession);
+            };
+            let media = relay_media(ctx, &*sessions, &owner, &partner, msg).await?;
+            // Stickers that cannot go over as stickers go over as their name.
+            let named = with_sticker_names(&clean.content, &msg.sticker_items);
+            let content = if media.stickers.is_empty() {
+                named.clone()
+            } else {
+                clean.content.clone()
+            };
+            if content.is_empty() && media.files.is_empty() && media.stickers.is_empty() {
+                notify_refused(ctx, chan_id, &media.refused).await?;
+                return Ok(());
+            }
+
+            let replied_to = msg.message_reference.as_ref().and_then(|r| r.message_id);
+            let reply = match replied_to {
+                Some(replied_to) => {
+                    counterpart(&*sessions, replied_to, target_chan_id, now).await?
+                }
+                None => None,
+            };
+            let outgoing = Outgoing {
+                target: target_chan_id,
+                lobby: partner.lobby,
+                guild: partner.guild,
+                persona,
+                content,
+                named,
+                files: media.files,
+                stickers: media.stickers,
+                reply,
+                suppress_embeds: config.link_policy.suppress_embeds,
+            };
+            let mirror = deliver(ctx, &outgoing).await?;
+            let expires_at = now + config.relay_ttl.as_secs();
+            sessions
+                .link_relay((chan_id, msg.id), (target_chan_id, mirror.id), expires_at)
+                .await?;
+            sessions.touch(owner.id, now).await?;
+            notify_stripped(ctx, chan_id, &clean).await?;
+            notify_refused(ctx, chan_id, &media.refused).await?;
+            strike_filtered(ctx, owner.id, &clean, now).await?;
+        }
+        None => {
+            chan_id
+                .say(
+                    &ctx.http,
+                    "You are not connected to anyone Please wait until someone connect",
+                )
+                .await?;
+            msg.delete(&ctx.http).await?;
+        }
+    };
+    Ok(())
+}
+
+/// A relayed message on its way to the partner's thread.
+struct Outgoing {
+    target: ChannelId,
+    /// The partner's lobby and server.
+    lobby: Option<ChannelId>,
+    guild: Option<GuildId>,
+    /// Who the sender is shown as.
+    persona: Persona,
+    content: String,
+    /// `content` with the stickers named, for when they cannot be sent.
+    named: String,
+    files: Vec<CreateAttachment>,
+    stickers: Vec<StickerId>,
+    /// The message in `target` this replies to.
+    reply: Option<MessageRef>,
+    suppress_embeds: bool,
+}
+
+/// Posts `out` through the partner's lobby webhook, or as the bot if there is
+/// none.
+async fn deliver(ctx: &Context, out: &Outgoing) -> Result<Message, GenericError> {
+    if let Some(lobby) = out.lobby {
+        if let Some(webhook) = webhooks::lobby_webhook(ctx, lobby).await? {
+            match execute(ctx, &webhook, out).await {
+                Ok(Some(message)) => return Ok(message),
+                Ok(None) => {}
+                Err(e) if webhooks::forget_if_gone(lobby, &e) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    send_as_bot(ctx, out).await
+}
+
+async fn execute(
+    ctx: &Context,
+    webhook: &Webhook,
+    out: &Outgoing,
+) -> Result<Option<Message>, serenity::Error> {
+    // Webhooks can neither send stickers nor reply, so stickers are named
+    // and replies link to what they answer.
+    let content = match (out.reply, out.guild) {
+        (Some((channel, message)), Some(guild)) => format!(
+            "{REPLY_PREFIX}https://discord.com/channels/{guild}/{channel}/{message}\n{}",
+            out.named
+        ),
+        _ => out.named.clone(),
+    };
+    let mut builder = ExecuteWebhook::new()
+        .content(content)
+        .username(&out.persona.name)
+        .avatar_url(&out.persona.avatar_url)
+        .in_thread(out.target)
+        .allowed_mentions(CreateAllowedMentions::new())
+        .add_files(out.files.clone());
+    if out.suppress_embeds {
+        builder = builder.flags(MessageFlags::SUPPRESS_EMBEDS);
+    }
+    webhook.execute(&ctx.http, true, builder).await
+}
+
+async fn send_as_bot(ctx: &Context, out: &Outgoing) -> Result<Message, GenericError> {
+    // The text goes in the persona embed, where links do not unfold, so
+    // there is nothing to suppress.
+    let build = |content: &str, stickers: Vec<StickerId>| {
+        let mut message = CreateMessage::new()
+            .embed(persona_embed(&out.persona, content))
+            .allowed_mentions(CreateAllowedMentions::new())
+            .add_files(out.files.clone())
+            .sticker_ids(stickers);
+        if let Some(reply) = out.reply {
+            let mut reference = MessageReference::from(reply);
+            // Still relay the message if the original was deleted since.
+            reference.fail_if_not_exists = Some(false);
+            message = message.reference_message(reference);
+        }
+        message
+    };
+    let relayed = build(&out.content, out.stickers.clone());
+    match out.target.send_message(&ctx.http, relayed).await {
+        // Stickers from servers the bot is not in cannot be sent.
+        Err(e) if !out.stickers.is_empty() && is_invalid_sticker(&e) => {
+            let relayed = build(&out.named, vec![]);
+            Ok(out.target.send_message(&ctx.http, relayed).await?)
+        }
+        res => Ok(res?),
+    }
+}
+
+fn persona_embed(persona: &Persona, content: &str) -> CreateEmbed {
+    let embed = CreateEmbed::new()
+        .author(CreateEmbedAuthor::new(&persona.name).icon_url(&persona.avatar_url));
+    if content.is_empty() {
+        embed
+    } else {
+        embed.description(content)
+    }
+}
+
+async fn partner_of(
+    sessions: &dyn SessionStore,
+    owner: &User,
+) -> Result<Option<User>, GenericError> {
+    Ok(match owner.partner {
+        Some(partner) => sessions.load_user(partner).await?,
+        None => None,
+    })
+}
+
+/// What of a message's files and stickers can go to the partner.
+struct Media {
+    files: Vec<CreateAttachment>,
+    stickers: Vec<StickerId>,
+    /// Files left out, with why.
+    refused: Vec<(String, Refusal)>,
+}
+
+/// Downloads the files of `msg` that both users' server policies allow, if
+/// both users opted in to media. Stickers go along under the same terms.
+async fn relay_media(
+    ctx: &Context,
+    sessions: &dyn SessionStore,
+    owner: &User,
+    partner: &User,
+    msg: &Message,
+) -> Result<Media, GenericError> {
+    let mut media = Media {
+        files: vec![],
+        stickers: vec![],
+        refused: vec![],
+    };
+    if msg.attachments.is_empty() && msg.sticker_items.is_empty() {
+        return Ok(media);
+    }
+    let policies = media_policies(ctx, sessions, owner, partner).await?;
+
+    for attachment in &msg.attachments {
+        let checked = policies
+            .as_ref()
+            .map_err(Clone::clone)
+            .and_then(|policies| {
+                policies.iter().try_for_each(|p| {
+                    p.check(
+                        &attachment.filename,
+                        attachment.content_type.as_deref(),
+                        attachment.size,
+                    )
+                })
+            });
+        match checked {
+            Ok(()) => {
+                let data = attachment.download().await?;
+                media
+                    .files
+                    .push(CreateAttachment::bytes(data, attachment.filename.clone()));
+            }
+            Err(refusal) => media.refused.push((attachment.filename.clone(), refusal)),
+        }
+    }
+    if policies.is_ok() {
+        media.stickers = msg.sticker_items.iter().map(|s| s.id).collect();
+    }
+    Ok(media)
+}
+
+/// The policies of both users' servers, or why media cannot flow between
+/// them at all.
+async fn media_policies(
+    ctx: &Context,
+    sessions: &dyn SessionStore,
+    owner: &User,
+    partner: &User,
+) -> Result<Result<Vec<MediaPolicy>, Refusal>, GenericError> {
+    if !sessions.media_opted_in(owner.id).await? || !sessions.media_opted_in(partner.id).await? {
+        return Ok(Err(Refusal::NotOptedIn));
+    }
+    let default = &get_config(ctx).await.media_policy;
+    let mut policies = vec![];
+    for guild in [owner.guild, partner.guild] {
+        let policy = match guild {
+            Some(guild) => sessions.media_policy(guild).await?,
+            None => None,
+        };
+        policies.push(policy.unwrap_or_else(|| default.clone()));
+    }
+    if policies.iter().any(|p| !p.enabled) {
+        return Ok(Err(Refusal::Disabled));
+    }
+    Ok(Ok(policies))
+}
+
+/// `content` followed by a line naming each of `stickers`.
+fn with_sticker_names(content: &str, stickers: &[StickerItem]) -> String {
+    if stickers.is_empty() {
+        return content.to_string();
+    }
+    let names: Vec<String> = stickers
+        .iter()
+        .map(|s| format!("[sticker: {}]", s.name))
+        .collect();
+    format!("{content}\n{}", names.join(" ")).trim().to_string()
+}
+
+fn is_invalid_sticker(error: &serenity::Error) -> bool {
+    matches!(
+        error,
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(res)) if res.error.code == INVALID_STICKER
+    )
+}
+
+/// The message in `target` that corresponds to `message`: the copy of one of
+/// the owner's own messages, or the original of a relayed one.
+async fn counterpart(
+    sessions: &dyn SessionStore,
+    message: MessageId,
+    target: ChannelId,
+    now: u64,
+) -> Result<Option<MessageRef>, GenericError> {
+    let linked = match sessions.mirror_of(message, now).await? {
+        Some(mirror) => Some(mirror),
+        None => sessions.source_of(message, now).await?,
+    };
+    Ok(linked.filter(|(channel, _)| *channel == target))
+}
+
+/// Carries an edit over to the relayed copy while the pair is still together.
+pub async fn relay_edit(ctx: &Context, event: &MessageUpdateEvent) -> Result<(), GenericError> {
+    // Updates without content are Discord adding link previews.
+    let Some(content) = &event.content else {
+        return Ok(());
+    };
+    let sessions = get_sessions(ctx).await;
+    let Some((owner, (target, mirror))) =
+        live_mirror(&*sessions, event.channel_id, event.id).await?
+    else {
+        return Ok(());
+    };
+    let config = get_config(ctx).await;
+    let clean = sanitize(content, &config.link_policy);
+    let existing = target.message(&ctx.http, mirror).await?;
+    if existing.webhook_id.is_some() {
+        let Some(webhook) = mirror_webhook(ctx, &*sessions, &owner).await? else {
+            return Ok(());
+        };
+        // Keep the link to the message it replied to.
+        let content = match existing.content.lines().next() {
+            Some(reply) if reply.starts_with(REPLY_PREFIX) => {
+                format!("{reply}\n{}", clean.content)
+            }
+            _ => clean.content.clone(),
+        };
+        webhook
+            .edit_message(
+                &ctx.http,
+                mirror,
+                EditWebhookMessage::new()
+                    .content(content)
+                    .in_thread(target)
+                    .allowed_mentions(CreateAllowedMentions::new()),
+            )
+            .await?;
+    } else if let Some(persona) = Persona::of(&owner) {
+        target
+            .edit_message(
+                &ctx.http,
+                mirror,
+                EditMessage::new()
+                    .embed(persona_embed(&persona, &clean.content))
+                    .allowed_mentions(CreateAllowedMentions::new()),
+            )
+            .await?;
+    }
+    notify_stripped(ctx, event.channel_id, &clean).await?;
+    strike_filtered(ctx, owner.id, &clean, now_secs()).await
+}
+
+/// Deletes the relayed copy of a deleted message while the pair is still
+/// together.
+pub async fn relay_delete(
+    ctx: &Context,
+    channel: ChannelId,
+    message: MessageId,
+) -> Result<(), GenericError> {
+    let sessions = get_sessions(ctx).await;
+    let Some((owner, (target, mirror))) = live_mirror(&*sessions, channel, message).await? else {
+        return Ok(());
+    };
+    // Webhook messages can be deleted through the webhook without Manage
+    // Messages.
+    let existing = target.message(&ctx.http, mirror).await?;
+    if existing.webhook_id.is_some() {
+        if let Some(webhook) = mirror_webhook(ctx, &*sessions, &owner).await? {
+            webhook
+                .delete_message(&ctx.http, Some(target), mirror)
+                .await?;
+            return Ok(());
+        }
+    }
+    target.delete_message(&ctx.http, mirror).await?;
+    Ok(())
+}
+
+/// The relayed copy of `message`, if it went to the partner the owner of
+/// `channel` is still talking to, along with that owner.
+async fn live_mirror(
+    sessions: &dyn SessionStore,
+    channel: ChannelId,
+    message: MessageId,
+) -> Result<Option<(User, MessageRef)>, GenericError> {
+    let Some(mirror) = sessions.mirror_of(message, now_secs()).await? else {
+        return Ok(None);
+    };
+    let Some(owner) = sessions.user_by_channel(channel).await? else {
+        return Ok(None);
+    };
+    if owner.partner_channel != Some(mirror.0) {
+        return Ok(None);
+    }
+    Ok(Some((owner, mirror)))
+}
+
+/// The webhook `owner`'s messages reach their partner through.
+async fn mirror_webhook(
+    ctx: &Context,
+    sessions: &dyn SessionStore,
+    owner: &User,
+) -> Result<Option<Webhook>, GenericError> {
+    let lobby = partner_of(sessions, owner).await?.and_then(|p| p.lobby);
+    match lobby {
+        Some(lobby) => webhooks::lobby_webhook(ctx, lobby).await,
+        None => Ok(None),
+    }
+}
+
+/// Mirrors the owner adding or removing a unicode reaction onto the
+/// counterpart of the message in the partner's thread. Custom emoji may come
+/// from servers the bot is not in, so they stay on this side.
+pub async fn relay_reaction(
+    ctx: &Context,
+    reaction: &Reaction,
+    added: bool,
+) -> Result<(), GenericError> {
+    let ReactionType::Unicode(_) = reaction.emoji else {
+        return Ok(());
+    };
+    let sessions = get_sessions(ctx).await;
+    let Some(owner) = sessions.user_by_channel(reaction.channel_id).await? else {
+        return Ok(());
+    };
+    // Also skips the bot's own mirrored reactions.
+    if reaction.user_id != Some(owner.id) {
+        return Ok(());
+    }
+    let Some(target) = owner.partner_channel else {
+        return Ok(());
+    };
+    let linked = counterpart(&*sessions, reaction.message_id, target, now_secs()).await?;
+    let Some((_, message)) = linked else {
+        return Ok(());
+    };
+    if added {
+        target
+            .create_reaction(&ctx.http, message, reaction.emoji.clone())
+            .await?;
+    } else {
+        target
+            .delete_reaction(&ctx.http, message, None, reaction.emoji.clone())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Shows the partner that the owner is typing, at most once per
+/// [`TYPING_INTERVAL`] per thread.
+pub async fn relay_typing(ctx: &Context, event: &TypingStartEvent) -> Result<(), GenericError> {
+    // Throttled before the store is asked, since this fires for every
+    // channel the bot can see.
+    if !TYPING.allow(event.channel_id, Instant::now()) {
+        return Ok(());
+    }
+    let sessions = get_sessions(ctx).await;
+    let Some(owner) = sessions.user_by_channel(event.channel_id).await? else {
+        return Ok(());
+    };
+    if owner.id != event.user_id {
+        return Ok(());
+    }
+    if let Some(target) = owner.partner_channel {
+        target.broadcast_typing(&ctx.http).await?;
+    }
+    Ok(())
+}
+
+/// Tells the sender which of their files were not relayed, if any.
+async fn notify_refused(
+    ctx: &Context,
+    channel: ChannelId,
+    refused: &[(String, Refusal)],
+) -> Result<(), GenericError> {
+    if refused.is_empty() {
+        return Ok(());
+    }
+    let files: Vec<String> = refused
+        .iter()
+        .map(|(name, refusal)| format!("{name} ({refusal})"))
+        .collect();
+    channel
+        .say(
+            &ctx.http,
+            format!("Not sent to your partner: {}", files.join(", ")),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Tells the sender what was taken out of their message, if anything.
+async fn notify_stripped(
+    ctx: &Context,
+    channel: ChannelId,
+    clean: &Sanitized,
+) -> Result<(), GenericError> {
+    if clean.stripped.is_empty() {
+        return Ok(());
+    }
+    let reasons: Vec<String> = clean.stripped.iter().map(|r| r.to_string()).collect();
+    channel
+        .say(
+            &ctx.http,
+            format!("Part of your message was removed: {}", reasons.join(", ")),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Gives `user` a strike if the filter took out an invite or a blocklisted
+/// link, whether they sent it or edited it in.
+async fn strike_filtered(
+    ctx: &Context,
+    user: UserId,
+    clean: &Sanitized,
+    now: u64,
+) -> Result<(), GenericError> {
+    let offences: Vec<String> = clean
+        .stripped
+        .iter()
+        .filter(|r| r.is_offence())
+        .map(|r| r.to_string())
+        .collect();
+    if offences.is_empty() {
+        return Ok(());
+    }
+    let strike = Strike {
+        user,
+        reason: format!("filtered message ({})", offences.join(", ")),
+        issued_by: None,
+        at: now,
+    };
+    strikes::add(ctx, strike).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttle_allows_one_event_per_channel_per_interval() {
+        let throttle = Throttle::new(Duration::from_secs(8));
+        let (a, b) = (ChannelId::new(1), ChannelId::new(2));
+        let start = Instant::now();
+
+        assert!(throttle.allow(a, start));
+        assert!(!throttle.allow(a, start + Duration::from_secs(7)));
+        assert!(throttle.allow(b, start + Duration::from_secs(7)));
+        assert!(throttle.allow(a, start + Duration::from_secs(8)));
+    }
+}
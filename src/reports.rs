@@ -0,0 +1,361 @@
+//! Reports of abusive strangers. A report keeps who reported whom, why, and
+//! the end of their chat as evidence, and is posted to the moderator channel
+//! where a moderator bans, warns or dismisses it. Both the report and how it
+//! was handled are kept in the session store.
+
+use serenity::all::{
+    ChannelId, Colour, Context, CreateActionRow, CreateAttachment, CreateEmbed, CreateMessage,
+    GetMessages, GuildId, Message, UserId,
+};
+
+use crate::bans::{self, Ban, BanScope};
+use crate::components::Button;
+use crate::config::get_config;
+use crate::error::GenericError;
+use crate::strikes::{self, Strike};
+use crate::{get_sessions, now_secs};
+
+/// Longest a single message is quoted in the evidence.
+const MAX_LINE_CHARS: usize = 300;
+
+/// Room for the evidence preview in the moderator embed, which also gets the
+/// whole transcript as a file.
+const MAX_PREVIEW_CHARS: usize = 3500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    Harassment,
+    Sexual,
+    Hate,
+    Spam,
+    Underage,
+    Other,
+}
+
+impl Reason {
+    pub const ALL: [Reason; 6] = [
+        Reason::Harassment,
+        Reason::Sexual,
+        Reason::Hate,
+        Reason::Spam,
+        Reason::Underage,
+        Reason::Other,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Reason::Harassment => "harassment",
+            Reason::Sexual => "sexual",
+            Reason::Hate => "hate",
+            Reason::Spam => "spam",
+            Reason::Underage => "underage",
+            Reason::Other => "other",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Reason> {
+        Reason::ALL.into_iter().find(|r| r.as_str() == value)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Reason::Harassment => "Harassment or bullying",
+            Reason::Sexual => "Unwanted sexual content",
+            Reason::Hate => "Hate speech",
+            Reason::Spam => "Spam or advertising",
+            Reason::Underage => "Seems to be underage",
+            Reason::Other => "Something else",
+        }
+    }
+}
+
+/// What a moderator decided about a report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Ban,
+    Warn,
+    Dismiss,
+}
+
+impl Outcome {
+    pub const ALL: [Outcome; 3] = [Outcome::Ban, Outcome::Warn, Outcome::Dismiss];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Ban => "ban",
+            Outcome::Warn => "warn",
+            Outcome::Dismiss => "dismiss",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Outcome> {
+        Outcome::ALL.into_iter().find(|o| o.as_str() == value)
+    }
+
+    fn past_tense(self) -> &'static str {
+        match self {
+            Outcome::Ban => "Banned",
+            Outcome::Warn => "Warned",
+            Outcome::Dismiss => "Dismissed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    pub outcome: Outcome,
+    pub moderator: UserId,
+    /// When the moderator decided (unix seconds).
+    pub at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub id: u64,
+    pub reporter: UserId,
+    pub reported: UserId,
+    pub reason: Reason,
+    /// The reporter's thread the report was made from.
+    pub thread: ChannelId,
+    /// The server `thread` is in.
+    pub guild: GuildId,
+    /// The last messages of the chat, one per line, oldest first.
+    pub evidence: String,
+    pub created_at: u64,
+    /// `None` until a moderator handled the report.
+    pub resolution: Option<Resolution>,
+}
+
+/// Files a report of `reported` by `reporter` from `thread` in `guild`.
+/// Returns the report's number, or `None` if no moderator channel is set up.
+pub async fn file(
+    ctx: &Context,
+    reporter: UserId,
+    reported: UserId,
+    reason: Reason,
+    thread: ChannelId,
+    guild: GuildId,
+) -> Result<Option<u64>, GenericError> {
+    let config = get_config(ctx).await;
+    let Some(mod_channel) = config.mod_channel else {
+        return Ok(None);
+    };
+    let evidence = capture(ctx, thread, reporter, config.report_evidence).await?;
+    let sessions = get_sessions(ctx).await;
+    let report = Report {
+        id: sessions.next_report_id().await?,
+        reporter,
+        reported,
+        reason,
+        thread,
+        guild,
+        evidence,
+        created_at: now_secs(),
+        resolution: None,
+    };
+    sessions.save_report(&report).await?;
+    mod_channel
+        .send_message(&ctx.http, review_message(&report))
+        .await?;
+    Ok(Some(report.id))
+}
+
+/// The last `limit` messages of `thread` as evidence lines. Notices from the
+/// bot itself are left out.
+async fn capture(
+    ctx: &Context,
+    thread: ChannelId,
+    reporter: UserId,
+    limit: u8,
+) -> Result<String, GenericError> {
+    let messages = thread
+        .messages(&ctx.http, GetMessages::new().limit(limit))
+        .await?;
+    // Discord returns the newest message first.
+    let lines: Vec<String> = messages
+        .iter()
+        .rev()
+        .filter_map(|m| message_line(m, reporter))
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+fn message_line(message: &Message, reporter: UserId) -> Option<String> {
+    let files = message.attachments.len() + message.sticker_items.len();
+    if message.author.id == reporter {
+        return evidence_line("Reporter", &message.content, files);
+    }
+    if message.webhook_id.is_some() {
+        return evidence_line("Stranger", &message.content, files);
+    }
+    // Relays sent as the bot carry the stranger's persona as the embed author.
+    let embed = message.embeds.first().filter(|e| e.author.is_some())?;
+    let text = embed.description.as_deref().unwrap_or_default();
+    evidence_line("Stranger", text, files)
+}
+
+/// One line of evidence, with long messages cut short. `None` for messages
+/// with nothing to show.
+fn evidence_line(author: &str, text: &str, files: usize) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut line = format!("{author}:");
+    if !text.is_empty() {
+        line.push(' ');
+        line.extend(text.chars().take(MAX_LINE_CHARS));
+        if text.chars().count() > MAX_LINE_CHARS {
+            line.push('…');
+        }
+    }
+    match files {
+        0 if text.is_empty() => return None,
+        0 => {}
+        1 => line.push_str(" [1 file]"),
+        n => line.push_str(&format!(" [{n} files]")),
+    }
+    Some(line)
+}
+
+/// As many of the last whole lines of `evidence` as fit in `max` characters.
+fn preview(evidence: &str, max: usize) -> String {
+    let mut kept = vec![];
+    let mut len = 0;
+    for line in evidence.lines().rev() {
+        len += line.chars().count() + 1;
+        if len > max {
+            break;
+        }
+        kept.push(line);
+    }
+    kept.reverse();
+    kept.join("\n")
+}
+
+fn review_message(report: &Report) -> CreateMessage {
+    let preview = preview(&report.evidence, MAX_PREVIEW_CHARS);
+    let description = if preview.is_empty() {
+        "No messages were captured.".to_string()
+    } else {
+        format!("```\n{}\n```", preview.replace("```", "'''"))
+    };
+    let embed = CreateEmbed::new()
+        .title(format!("Report #{}", report.id))
+        .description(description)
+        .field("Reporter", format!("<@{0}> ({0})", report.reporter), true)
+        .field("Reported", format!("<@{0}> ({0})", report.reported), true)
+        .field("Reason", report.reason.label(), true)
+        .field("Thread", format!("<#{}>", report.thread), true)
+        .colour(Colour::ORANGE);
+    let buttons = Outcome::ALL
+        .into_iter()
+        .map(|outcome| Button::Review(report.id, outcome).build())
+        .collect();
+    CreateMessage::new()
+        .embed(embed)
+        .add_file(CreateAttachment::bytes(
+            report.evidence.as_bytes(),
+            format!("report-{}.txt", report.id),
+        ))
+        .components(vec![CreateActionRow::Buttons(buttons)])
+}
+
+/// Records `moderator`'s decision on report `id` and carries it out. Bans
+/// apply everywhere when a bot admin decides and otherwise only in the server
+/// the report came from. Returns what to show on the moderator message, or
+/// `None` if someone else already handled the report.
+pub async fn review(
+    ctx: &Context,
+    id: u64,
+    outcome: Outcome,
+    moderator: UserId,
+) -> Result<Option<String>, GenericError> {
+    let sessions = get_sessions(ctx).await;
+    let Some(report) = sessions.report(id).await? else {
+        return Ok(None);
+    };
+    let resolution = Resolution {
+        outcome,
+        moderator,
+        at: now_secs(),
+    };
+    if !sessions.resolve_report(id, &resolution).await? {
+        return Ok(None);
+    }
+
+    let mut result = format!("{} by <@{moderator}>", outcome.past_tense());
+    match outcome {
+        Outcome::Ban => {
+            let scope = if get_config(ctx).await.bot_admins.contains(&moderator) {
+                result.push_str(", everywhere");
+                BanScope::Global
+            } else {
+                result.push_str(", in the reporter's server");
+                BanScope::Guild(report.guild)
+            };
+            let ban = Ban {
+                user: report.reported,
+                scope,
+                reason: Some(format!("Report #{id}: {}", report.reason.label())),
+                banned_by: moderator,
+                created_at: resolution.at,
+                expires_at: None,
+            };
+            bans::impose(ctx, &ban).await?;
+        }
+        Outcome::Warn => {
+            let strike = Strike {
+                user: report.reported,
+                reason: format!("Report #{id}: {}", report.reason.label()),
+                issued_by: Some(moderator),
+                at: resolution.at,
+            };
+            strikes::add(ctx, strike).await?;
+        }
+        Outcome::Dismiss => {}
+    }
+    Ok(Some(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reasons_and_outcomes_round_trip() {
+        for reason in Reason::ALL {
+            assert_eq!(Reason::parse(reason.as_str()), Some(reason));
+        }
+        for outcome in Outcome::ALL {
+            assert_eq!(Outcome::parse(outcome.as_str()), Some(outcome));
+        }
+        assert_eq!(Reason::parse("nope"), None);
+        assert_eq!(Outcome::parse(""), None);
+    }
+
+    #[test]
+    fn evidence_lines_are_flat_and_short() {
+        assert_eq!(
+            evidence_line("Stranger", "hi\n  there", 0).as_deref(),
+            Some("Stranger: hi there")
+        );
+        assert_eq!(
+            evidence_line("Reporter", "", 2).as_deref(),
+            Some("Reporter: [2 files]")
+        );
+        assert_eq!(evidence_line("Stranger", " \n", 0), None);
+
+        let long = evidence_line("Stranger", &"a".repeat(400), 1).unwrap();
+        assert!(long.ends_with("a… [1 file]"), "{long}");
+        assert_eq!(
+            long.chars().count(),
+            "Stranger: ".len() + MAX_LINE_CHARS + "… [1 file]".chars().count()
+        );
+    }
+
+    #[test]
+    fn preview_keeps_the_last_whole_lines() {
+        let evidence = "first line\nsecond\nthird";
+        assert_eq!(preview(evidence, 100), evidence);
+        assert_eq!(preview(evidence, 13), "second\nthird");
+        assert_eq!(preview(evidence, 3), "");
+    }
+}
@@ -0,0 +1,311 @@
+//! Cleans up a message before it is relayed to the stranger: mentions are
+//! turned into plain text and links go through the [`LinkPolicy`].
+
+/// Which links may be relayed. Domains match themselves and their
+/// subdomains.
+#[derive(Debug, Clone)]
+pub struct LinkPolicy {
+    /// Drop Discord server invites.
+    pub block_invites: bool,
+    /// If not empty, only links to these domains are relayed.
+    pub allowed_domains: Vec<String>,
+    /// Links to these domains are never relayed.
+    pub blocked_domains: Vec<String>,
+    /// Relay messages without link previews.
+    pub suppress_embeds: bool,
+}
+
+impl Default for LinkPolicy {
+    fn default() -> LinkPolicy {
+        LinkPolicy {
+            block_invites: true,
+            allowed_domains: vec![],
+            blocked_domains: vec![],
+            suppress_embeds: true,
+        }
+    }
+}
+
+/// Why part of a message was taken out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stripped {
+    Invite,
+    /// A link to a blocklisted domain.
+    Blocked(String),
+    /// A link to a domain missing from the allowlist.
+    Unlisted(String),
+}
+
+impl Stripped {
+    /// Whether sending it counts against the sender. A link that is just not
+    /// on the allowlist is an honest mistake.
+    pub fn is_offence(&self) -> bool {
+        matches!(self, Stripped::Invite | Stripped::Blocked(_))
+    }
+}
+
+impl std::fmt::Display for Stripped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stripped::Invite => write!(f, "server invites are not allowed"),
+            Stripped::Blocked(host) | Stripped::Unlisted(host) => {
+                write!(f, "links to {host} are not allowed")
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Sanitized {
+    pub content: String,
+    /// Everything that was removed, once per reason.
+    pub stripped: Vec<Stripped>,
+}
+
+const LINK_REMOVED: &str = "[link removed]";
+
+/// Hosts whose links are invites; for the `(host, path)` pairs only paths
+/// under `path` are.
+const INVITE_HOSTS: &[(&str, &str)] = &[
+    ("discord.gg", "/"),
+    ("discord.com", "/invite/"),
+    ("discordapp.com", "/invite/"),
+];
+
+/// Where a link can start. Invites are also recognised without a scheme
+/// because Discord turns them into links anyway.
+const LINK_STARTS: &[&str] = &[
+    "https://",
+    "http://",
+    "discord.gg/",
+    "discord.com/invite/",
+    "discordapp.com/invite/",
+];
+
+pub fn sanitize(content: &str, policy: &LinkPolicy) -> Sanitized {
+    let mut stripped = vec![];
+    let mut out = String::with_capacity(content.len());
+    for word in content.split_inclusive(char::is_whitespace) {
+        let mut rest = word;
+        while let Some((start, end)) = find_link(rest) {
+            match check_link(&rest[start..end], policy) {
+                Some(reason) => {
+                    out.push_str(&rest[..start]);
+                    out.push_str(LINK_REMOVED);
+                    if !stripped.contains(&reason) {
+                        stripped.push(reason);
+                    }
+                }
+                None => out.push_str(&rest[..end]),
+            }
+            rest = &rest[end..];
+        }
+        out.push_str(rest);
+    }
+    Sanitized {
+        content: neutralize_mentions(&out),
+        stripped,
+    }
+}
+
+/// The byte range of the first link in `word`, without trailing punctuation.
+/// It ends where another link glued onto it begins.
+fn find_link(word: &str) -> Option<(usize, usize)> {
+    let lower = word.to_ascii_lowercase();
+    let start = LINK_STARTS.iter().filter_map(|s| lower.find(s)).min()?;
+    let end = next_link(&lower, start).unwrap_or(word.len());
+    let link = word[start..end].trim_end().trim_end_matches([
+        '>', ')', ']', '.', ',', '!', '?', '"', '\'', '*', '_', '~', '|',
+    ]);
+    Some((start, start + link.len()))
+}
+
+/// Where a second link starts after the one at `start`: a link start right
+/// after punctuation like `,`, not one inside the first link's own URL.
+fn next_link(lower: &str, start: usize) -> Option<usize> {
+    LINK_STARTS
+        .iter()
+        .flat_map(|s| lower.match_indices(s))
+        .map(|(i, _)| i)
+        .filter(|&i| {
+            i > start
+                && lower[..i].ends_with(|c: char| !c.is_alphanumeric() && !".-/:@".contains(c))
+        })
+        .min()
+}
+
+fn check_link(link: &str, policy: &LinkPolicy) -> Option<Stripped> {
+    let lower = link.to_ascii_lowercase();
+    let rest = lower
+        .strip_prefix("https://")
+        .or_else(|| lower.strip_prefix("http://"))
+        .unwrap_or(&lower);
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(authority_end);
+    let host = authority.rsplit('@').next().unwrap_or(authority);
+    let host = host.split(':').next().unwrap_or(host).trim_end_matches('.');
+
+    let is_invite = INVITE_HOSTS
+        .iter()
+        .any(|(h, p)| matches_domain(host, h) && path.starts_with(p));
+    if is_invite && policy.block_invites {
+        return Some(Stripped::Invite);
+    }
+    if policy
+        .blocked_domains
+        .iter()
+        .any(|d| matches_domain(host, d))
+    {
+        return Some(Stripped::Blocked(host.to_string()));
+    }
+    let allowed = policy.allowed_domains.is_empty()
+        || policy
+            .allowed_domains
+            .iter()
+            .any(|d| matches_domain(host, d));
+    if !allowed {
+        return Some(Stripped::Unlisted(host.to_string()));
+    }
+    None
+}
+
+fn matches_domain(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.ends_with('.'))
+}
+
+/// Turns user, role and channel mentions and `@everyone`/`@here` into text
+/// that renders the same but pings nobody, even if mentions were allowed.
+fn neutralize_mentions(content: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(open) = rest.find('<') {
+        out.push_str(&rest[..open]);
+        rest = &rest[open..];
+        match mention(rest) {
+            Some((text, len)) => {
+                out.push_str(text);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('<');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out.replace("@everyone", "@\u{200B}everyone")
+        .replace("@here", "@\u{200B}here")
+}
+
+/// The plain text for a mention at the start of `s`, and its length.
+fn mention(s: &str) -> Option<(&'static str, usize)> {
+    let (text, prefix) = if s.starts_with("<@&") {
+        ("@role", 3)
+    } else if s.starts_with("<@!") {
+        ("@user", 3)
+    } else if s.starts_with("<@") {
+        ("@user", 2)
+    } else if s.starts_with("<#") {
+        ("#channel", 2)
+    } else {
+        return None;
+    };
+    let digits = s[prefix..].bytes().take_while(u8::is_ascii_digit).count();
+    if digits == 0 || s.as_bytes().get(prefix + digits) != Some(&b'>') {
+        return None;
+    }
+    Some((text, prefix + digits + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clean(content: &str) -> String {
+        sanitize(content, &LinkPolicy::default()).content
+    }
+
+    #[test]
+    fn mentions_become_plain_text() {
+        assert_eq!(
+            clean("hi <@123> <@!45> <@&6> <#7> <@> <3 <@x>"),
+            "hi @user @user @role #channel <@> <3 <@x>"
+        );
+        assert_eq!(clean("@everyone @here"), "@\u{200B}everyone @\u{200B}here");
+    }
+
+    #[test]
+    fn invites_are_stripped_with_or_without_scheme() {
+        for invite in [
+            "https://discord.gg/abc",
+            "discord.gg/abc",
+            "HTTPS://Discord.com/invite/abc",
+            "(https://ptb.discordapp.com/invite/abc).",
+            "https://ok.com,https://discord.gg/abc",
+            "discord.gg/ok,discord.gg/abc",
+        ] {
+            let out = sanitize(&format!("join {invite} now"), &LinkPolicy::default());
+            assert!(!out.content.contains("abc"), "{invite}: {}", out.content);
+            assert_eq!(out.stripped, vec![Stripped::Invite]);
+        }
+        assert_eq!(
+            clean("https://ok.com,https://discord.gg/abc!"),
+            "https://ok.com,[link removed]!"
+        );
+        assert_eq!(
+            clean("see https://discord.com/channels/1/2 ok"),
+            "see https://discord.com/channels/1/2 ok"
+        );
+    }
+
+    #[test]
+    fn domain_lists_match_subdomains() {
+        let policy = LinkPolicy {
+            blocked_domains: vec!["bad.com".to_string()],
+            ..LinkPolicy::default()
+        };
+        let out = sanitize(
+            "https://www.bad.com/x https://notbad.com https://user@bad.com:80",
+            &policy,
+        );
+        assert_eq!(
+            out.content,
+            "[link removed] https://notbad.com [link removed]"
+        );
+        assert_eq!(
+            out.stripped,
+            vec![
+                Stripped::Blocked("www.bad.com".to_string()),
+                Stripped::Blocked("bad.com".to_string())
+            ]
+        );
+        assert!(out.stripped.iter().all(Stripped::is_offence));
+
+        let policy = LinkPolicy {
+            allowed_domains: vec!["youtube.com".to_string()],
+            ..LinkPolicy::default()
+        };
+        let out = sanitize("https://m.youtube.com/watch\nhttp://other.org", &policy);
+        assert_eq!(out.content, "https://m.youtube.com/watch\n[link removed]");
+        assert_eq!(
+            out.stripped,
+            vec![Stripped::Unlisted("other.org".to_string())]
+        );
+        assert!(!out.stripped[0].is_offence());
+    }
+
+    #[test]
+    fn plain_text_is_untouched() {
+        let text = "just chatting, email me at a@b.c <3";
+        assert_eq!(
+            sanitize(text, &LinkPolicy::default()),
+            Sanitized {
+                content: text.to_string(),
+                stripped: vec![],
+            }
+        );
+    }
+}
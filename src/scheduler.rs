@@ -0,0 +1,225 @@
+//! Background sweep over every session: takes people out of the queue after
+//! waiting too long, warns pairs that went quiet and ends chats that stay
+//! quiet past the hard limit.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serenity::all::{Context, UserId};
+
+use crate::components::describe;
+use crate::config::{get_config, Config};
+use crate::error::{report_error, ErrorContext};
+use crate::store::User;
+use crate::{close_thread, end_pairing, get_sessions, now_secs, GenericError};
+
+/// How often the sweep runs. Timeouts fire up to this much late.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    /// Waited for a partner longer than the queue timeout.
+    ExpireWait(UserId),
+    /// Quiet past the warning threshold and not warned since.
+    WarnIdle(UserId, UserId),
+    /// Quiet past the hard limit.
+    EndIdle(UserId),
+}
+
+/// Decides what is due for everyone in `users`, visiting each pair once.
+fn plan(users: &[User], now: u64, config: &Config) -> Vec<Action> {
+    let by_id: HashMap<UserId, &User> = users.iter().map(|u| (u.id, u)).collect();
+    let mut actions = vec![];
+    for user in users {
+        let Some(partner) = user.partner else {
+            if now.saturating_sub(user.last_active) >= config.queue_timeout.as_secs() {
+                actions.push(Action::ExpireWait(user.id));
+            }
+            continue;
+        };
+        // Half-gone pairs are left to the reconciliation on startup.
+        let Some(partner) = by_id.get(&partner).filter(|p| p.partner == Some(user.id)) else {
+            continue;
+        };
+        if user.id > partner.id {
+            continue;
+        }
+
+        let idle = now.saturating_sub(user.last_active.max(partner.last_active));
+        if idle >= config.idle_limit.as_secs() {
+            actions.push(Action::EndIdle(user.id));
+        } else if idle >= config.idle_warning.as_secs()
+            && !(user.idle_warned && partner.idle_warned)
+        {
+            actions.push(Action::WarnIdle(user.id, partner.id));
+        }
+    }
+    actions
+}
+
+/// Starts the sweep loop. Call once per process.
+pub fn spawn(ctx: &Context) {
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = sweep(&ctx).await {
+                report_error(&ctx, &ErrorContext::new("scheduler"), &e).await;
+            }
+        }
+    });
+}
+
+async fn sweep(ctx: &Context) -> Result<(), GenericError> {
+    let sessions = get_sessions(ctx).await;
+    let config = get_config(ctx).await;
+    let users: Vec<User> = sessions
+        .all_users()
+        .await?
+        .into_iter()
+        .filter_map(|(_, user)| user)
+        .collect();
+
+    for action in plan(&users, now_secs(), &config) {
+        let user = match action {
+            Action::ExpireWait(id) | Action::WarnIdle(id, _) | Action::EndIdle(id) => id,
+        };
+        if let Err(e) = run(ctx, action, &config).await {
+            report_error(ctx, &ErrorContext::new("scheduler").user(user), &e).await;
+        }
+    }
+    Ok(())
+}
+
+async fn run(ctx: &Context, action: Action, config: &Config) -> Result<(), GenericError> {
+    let sessions = get_sessions(ctx).await;
+    match action {
+        Action::ExpireWait(id) => {
+            let Some(user) = sessions.load_user(id).await? else {
+                return Ok(());
+            };
+            // Someone may have paired them since the sweep read the queue.
+            if user.partner.is_some() || !sessions.dequeue(&user).await? {
+                return Ok(());
+            }
+            user.channel
+                .say(
+                    &ctx.http,
+                    format!(
+                        "Nobody showed up within {}, so you were taken out of the queue. Use /start to try again",
+                        describe(config.queue_timeout)
+                    ),
+                )
+                .await?;
+            close_thread(ctx, user.channel).await?;
+        }
+        Action::WarnIdle(a, b) => {
+            let warning = format!(
+                "This chat has been quiet for {}. It will end after {} without messages",
+                describe(config.idle_warning),
+                describe(config.idle_limit)
+            );
+            for id in [a, b] {
+                sessions.mark_idle_warned(id).await?;
+                if let Some(user) = sessions.load_user(id).await? {
+                    user.channel.say(&ctx.http, &warning).await?;
+                }
+            }
+        }
+        Action::EndIdle(id) => {
+            let Some((user, partner)) = end_pairing(&*sessions, id).await? else {
+                return Ok(());
+            };
+            let notice = format!(
+                "This chat ended after {} without messages. Use /start to meet someone new",
+                describe(config.idle_limit)
+            );
+            // Both threads are closed even if one side fails, as neither
+            // session exists anymore.
+            let mut errors = vec![];
+            for side in [&user, &partner] {
+                if let Err(e) = side.channel.say(&ctx.http, &notice).await {
+                    errors.push((side, e.into()));
+                }
+                if let Err(e) = close_thread(ctx, side.channel).await {
+                    errors.push((side, e));
+                }
+            }
+            for (side, e) in errors {
+                let context = ErrorContext::new("scheduler")
+                    .user(side.id)
+                    .channel(side.channel);
+                report_error(ctx, &context, &e).await;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            queue_timeout: Duration::from_secs(100),
+            idle_warning: Duration::from_secs(50),
+            idle_limit: Duration::from_secs(200),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn expires_long_waits_only() {
+        let users = [User::test(1), User::test(2).with_last_active(50)];
+        assert_eq!(
+            plan(&users, 120, &config()),
+            vec![Action::ExpireWait(UserId::new(1))]
+        );
+    }
+
+    #[test]
+    fn warns_quiet_pairs_once_and_ends_them_later() {
+        let pair = [
+            User::test(1).with_partner(2),
+            User::test(2).with_partner(1).with_last_active(10),
+        ];
+        assert_eq!(plan(&pair, 40, &config()), vec![]);
+        assert_eq!(
+            plan(&pair, 60, &config()),
+            vec![Action::WarnIdle(UserId::new(1), UserId::new(2))]
+        );
+
+        let warned = [
+            User::test(1).with_partner(2).with_idle_warned(true),
+            User::test(2)
+                .with_partner(1)
+                .with_last_active(10)
+                .with_idle_warned(true),
+        ];
+        assert_eq!(plan(&warned, 60, &config()), vec![]);
+        assert_eq!(
+            plan(&warned, 210, &config()),
+            vec![Action::EndIdle(UserId::new(1))]
+        );
+
+        // A message from either side clears that side's flag and the clock.
+        let resumed = [
+            User::test(1).with_partner(2).with_idle_warned(true),
+            User::test(2).with_partner(1).with_last_active(100),
+        ];
+        assert_eq!(plan(&resumed, 120, &config()), vec![]);
+        assert_eq!(
+            plan(&resumed, 160, &config()),
+            vec![Action::WarnIdle(UserId::new(1), UserId::new(2))]
+        );
+    }
+
+    #[test]
+    fn skips_half_gone_pairs() {
+        let users = [User::test(1).with_partner(2)];
+        assert_eq!(plan(&users, 1000, &config()), vec![]);
+    }
+}
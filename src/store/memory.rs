@@ -0,0 +1,644 @@
+//! In-process [`SessionStore`] for tests and single-process dev mode. One
+//! mutex around the whole state gives the same atomicity the Redis scripts do.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use serenity::async_trait;
+
+use super::{MessageRef, SessionStore, StoreResult, User};
+use crate::bans::{Ban, BanScope};
+use crate::media::MediaPolicy;
+use crate::reports::{Report, Resolution};
+use crate::strikes::Strike;
+use crate::StartOptions;
+
+#[derive(Default)]
+struct State {
+    users: HashMap<UserId, User>,
+    channels: HashMap<ChannelId, UserId>,
+    /// Waiting users with the time they joined, kept in queue order.
+    queue: Vec<(u64, UserId)>,
+    chat_counter: u64,
+    /// Relayed messages both ways, with when the link expires.
+    mirrors: HashMap<MessageId, (MessageRef, u64)>,
+    sources: HashMap<MessageId, (MessageRef, u64)>,
+    media_policies: HashMap<GuildId, MediaPolicy>,
+    media_opt_ins: HashSet<UserId>,
+    searches: HashMap<UserId, StartOptions>,
+    report_counter: u64,
+    reports: HashMap<u64, Report>,
+    bans: HashMap<(BanScope, UserId), Ban>,
+    strikes: HashMap<UserId, Vec<Strike>>,
+    /// `(user, blocked)` pairs.
+    blocks: HashSet<(UserId, UserId)>,
+}
+
+impl State {
+    fn queue_position(&self, id: UserId) -> Option<usize> {
+        self.queue.iter().position(|(_, queued)| *queued == id)
+    }
+
+    fn push_queue(&mut self, id: UserId, queued_at: u64) {
+        if let Some(pos) = self.queue_position(id) {
+            self.queue.remove(pos);
+        }
+        let pos = self
+            .queue
+            .partition_point(|(at, queued)| (*at, queued.get()) <= (queued_at, id.get()));
+        self.queue.insert(pos, (queued_at, id));
+    }
+
+    fn expire_relays(&mut self, now: u64) {
+        self.mirrors.retain(|_, (_, expires_at)| *expires_at > now);
+        self.sources.retain(|_, (_, expires_at)| *expires_at > now);
+    }
+
+    fn expire_bans(&mut self, now: u64) {
+        self.bans.retain(|_, ban| ban.is_active(now));
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<State>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        // A panic while holding the lock cannot leave the maps half-updated
+        // in a way later calls would trip over, so keep serving.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemoryStore {
+    async fn load_user(&self, id: UserId) -> StoreResult<Option<User>> {
+        Ok(self.state().users.get(&id).cloned())
+    }
+
+    async fn user_by_channel(&self, channel: ChannelId) -> StoreResult<Option<User>> {
+        let state = self.state();
+        Ok(state
+            .channels
+            .get(&channel)
+            .and_then(|id| state.users.get(id))
+            .cloned())
+    }
+
+    async fn all_users(&self) -> StoreResult<Vec<(UserId, Option<User>)>> {
+        Ok(self
+            .state()
+            .users
+            .values()
+            .map(|u| (u.id, Some(u.clone())))
+            .collect())
+    }
+
+    async fn waiting_users(&self) -> StoreResult<Vec<User>> {
+        let state = self.state();
+        Ok(state
+            .queue
+            .iter()
+            .filter_map(|(_, id)| state.users.get(id).cloned())
+            .collect())
+    }
+
+    async fn next_chat_number(&self) -> StoreResult<u64> {
+        let mut state = self.state();
+        state.chat_counter += 1;
+        Ok(state.chat_counter)
+    }
+
+    async fn enqueue(&self, user: &User, queued_at: u64) -> StoreResult<bool> {
+        let mut state = self.state();
+        if state.users.contains_key(&user.id) {
+            return Ok(false);
+        }
+        state.users.insert(user.id, user.clone());
+        state.channels.insert(user.channel, user.id);
+        state.push_queue(user.id, queued_at);
+        Ok(true)
+    }
+
+    async fn pair(&self, a: &User, b: &User, paired_at: u64) -> StoreResult<bool> {
+        let mut state = self.state();
+        let (Some(a_pos), Some(_)) = (state.queue_position(a.id), state.queue_position(b.id))
+        else {
+            return Ok(false);
+        };
+        let (Some(a_channel), Some(b_channel)) = (
+            state.users.get(&a.id).map(|u| u.channel),
+            state.users.get(&b.id).map(|u| u.channel),
+        ) else {
+            return Ok(false);
+        };
+        state.queue.remove(a_pos);
+        if let Some(b_pos) = state.queue_position(b.id) {
+            state.queue.remove(b_pos);
+        }
+        if let Some(user) = state.users.get_mut(&a.id) {
+            user.partner = Some(b.id);
+            user.partner_channel = Some(b_channel);
+            user.last_active = paired_at;
+            user.idle_warned = false;
+        }
+        if let Some(user) = state.users.get_mut(&b.id) {
+            user.partner = Some(a.id);
+            user.partner_channel = Some(a_channel);
+            user.last_active = paired_at;
+            user.idle_warned = false;
+        }
+        Ok(true)
+    }
+
+    async fn dequeue(&self, user: &User) -> StoreResult<bool> {
+        let mut state = self.state();
+        let Some(pos) = state.queue_position(user.id) else {
+            return Ok(false);
+        };
+        state.queue.remove(pos);
+        state.users.remove(&user.id);
+        state.channels.remove(&user.channel);
+        Ok(true)
+    }
+
+    async fn unpair(&self, a: &User, b: &User) -> StoreResult<bool> {
+        let mut state = self.state();
+        let paired = state.users.get(&a.id).and_then(|u| u.partner) == Some(b.id)
+            && state.users.get(&b.id).and_then(|u| u.partner) == Some(a.id);
+        if !paired {
+            return Ok(false);
+        }
+        state.users.remove(&a.id);
+        state.users.remove(&b.id);
+        state.channels.remove(&a.channel);
+        state.channels.remove(&b.channel);
+        Ok(true)
+    }
+
+    async fn requeue(&self, user: &User, partner: UserId, queued_at: u64) -> StoreResult<bool> {
+        let mut state = self.state();
+        match state.users.get_mut(&user.id) {
+            Some(stored) if stored.partner == Some(partner) => {
+                stored.partner = None;
+                stored.partner_channel = None;
+                stored.last_active = user.last_active;
+                stored.idle_warned = false;
+            }
+            _ => return Ok(false),
+        }
+        state.push_queue(user.id, queued_at);
+        Ok(true)
+    }
+
+    async fn skip(&self, user: &User, partner: &User, queued_at: u64) -> StoreResult<bool> {
+        let mut state = self.state();
+        let paired = state.users.get(&user.id).and_then(|u| u.partner) == Some(partner.id)
+            && state.users.get(&partner.id).and_then(|u| u.partner) == Some(user.id);
+        if !paired {
+            return Ok(false);
+        }
+        state.users.remove(&partner.id);
+        state.channels.remove(&partner.channel);
+        if let Some(stored) = state.users.get_mut(&user.id) {
+            stored.partner = None;
+            stored.partner_channel = None;
+            stored.wait_until = user.wait_until;
+            stored.last_active = user.last_active;
+            stored.idle_warned = false;
+        }
+        state.push_queue(user.id, queued_at);
+        Ok(true)
+    }
+
+    async fn touch(&self, id: UserId, at: u64) -> StoreResult<()> {
+        if let Some(user) = self.state().users.get_mut(&id) {
+            user.last_active = at;
+            user.idle_warned = false;
+        }
+        Ok(())
+    }
+
+    async fn mark_idle_warned(&self, id: UserId) -> StoreResult<()> {
+        if let Some(user) = self.state().users.get_mut(&id) {
+            user.idle_warned = true;
+        }
+        Ok(())
+    }
+
+    async fn link_relay(
+        &self,
+        source: MessageRef,
+        mirror: MessageRef,
+        expires_at: u64,
+    ) -> StoreResult<()> {
+        let mut state = self.state();
+        state.mirrors.insert(source.1, (mirror, expires_at));
+        state.sources.insert(mirror.1, (source, expires_at));
+        Ok(())
+    }
+
+    async fn mirror_of(&self, source: MessageId, now: u64) -> StoreResult<Option<MessageRef>> {
+        let mut state = self.state();
+        state.expire_relays(now);
+        Ok(state.mirrors.get(&source).map(|(mirror, _)| *mirror))
+    }
+
+    async fn source_of(&self, mirror: MessageId, now: u64) -> StoreResult<Option<MessageRef>> {
+        let mut state = self.state();
+        state.expire_relays(now);
+        Ok(state.sources.get(&mirror).map(|(source, _)| *source))
+    }
+
+    async fn media_policy(&self, guild: GuildId) -> StoreResult<Option<MediaPolicy>> {
+        Ok(self.state().media_policies.get(&guild).cloned())
+    }
+
+    async fn set_media_policy(&self, guild: GuildId, policy: &MediaPolicy) -> StoreResult<()> {
+        self.state().media_policies.insert(guild, policy.clone());
+        Ok(())
+    }
+
+    async fn media_opted_in(&self, user: UserId) -> StoreResult<bool> {
+        Ok(self.state().media_opt_ins.contains(&user))
+    }
+
+    async fn set_media_opt_in(&self, user: UserId, opted_in: bool) -> StoreResult<()> {
+        let mut state = self.state();
+        if opted_in {
+            state.media_opt_ins.insert(user);
+        } else {
+            state.media_opt_ins.remove(&user);
+        }
+        Ok(())
+    }
+
+    async fn save_search(&self, user: UserId, options: &StartOptions) -> StoreResult<()> {
+        self.state().searches.insert(user, options.clone());
+        Ok(())
+    }
+
+    async fn last_search(&self, user: UserId) -> StoreResult<Option<StartOptions>> {
+        Ok(self.state().searches.get(&user).cloned())
+    }
+
+    async fn next_report_id(&self) -> StoreResult<u64> {
+        let mut state = self.state();
+        state.report_counter += 1;
+        Ok(state.report_counter)
+    }
+
+    async fn save_report(&self, report: &Report) -> StoreResult<()> {
+        self.state().reports.insert(report.id, report.clone());
+        Ok(())
+    }
+
+    async fn report(&self, id: u64) -> StoreResult<Option<Report>> {
+        Ok(self.state().reports.get(&id).cloned())
+    }
+
+    async fn resolve_report(&self, id: u64, resolution: &Resolution) -> StoreResult<bool> {
+        match self.state().reports.get_mut(&id) {
+            Some(report) if report.resolution.is_none() => {
+                report.resolution = Some(resolution.clone());
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn add_ban(&self, ban: &Ban) -> StoreResult<()> {
+        self.state().bans.insert((ban.scope, ban.user), ban.clone());
+        Ok(())
+    }
+
+    async fn remove_ban(&self, user: UserId, scope: BanScope) -> StoreResult<bool> {
+        Ok(self.state().bans.remove(&(scope, user)).is_some())
+    }
+
+    async fn bans(&self, scope: BanScope, now: u64) -> StoreResult<Vec<Ban>> {
+        let mut state = self.state();
+        state.expire_bans(now);
+        let mut bans: Vec<Ban> = state
+            .bans
+            .values()
+            .filter(|b| b.scope == scope)
+            .cloned()
+            .collect();
+        bans.sort_by_key(|b| b.created_at);
+        Ok(bans)
+    }
+
+    async fn active_ban(
+        &self,
+        user: UserId,
+        guild: Option<GuildId>,
+        now: u64,
+    ) -> StoreResult<Option<Ban>> {
+        let mut state = self.state();
+        state.expire_bans(now);
+        let scopes = std::iter::once(BanScope::Global).chain(guild.map(BanScope::Guild));
+        Ok(scopes
+            .filter_map(|scope| state.bans.get(&(scope, user)))
+            .next()
+            .cloned())
+    }
+
+    async fn add_strike(&self, strike: &Strike) -> StoreResult<usize> {
+        let mut state = self.state();
+        let strikes = state.strikes.entry(strike.user).or_default();
+        strikes.push(strike.clone());
+        Ok(strikes.len())
+    }
+
+    async fn strikes(&self, user: UserId) -> StoreResult<Vec<Strike>> {
+        Ok(self.state().strikes.get(&user).cloned().unwrap_or_default())
+    }
+
+    async fn clear_strikes(&self, user: UserId) -> StoreResult<usize> {
+        Ok(self.state().strikes.remove(&user).map_or(0, |s| s.len()))
+    }
+
+    async fn block(&self, user: UserId, blocked: UserId) -> StoreResult<bool> {
+        Ok(self.state().blocks.insert((user, blocked)))
+    }
+
+    async fn blocks_involving(&self, user: UserId) -> StoreResult<HashSet<UserId>> {
+        Ok(self
+            .state()
+            .blocks
+            .iter()
+            .filter_map(|&(a, b)| match (a == user, b == user) {
+                (true, _) => Some(b),
+                (_, true) => Some(a),
+                _ => None,
+            })
+            .collect())
+    }
+
+    async fn forget(&self, id: UserId, channel: Option<ChannelId>) -> StoreResult<()> {
+        let mut state = self.state();
+        if let Some(pos) = state.queue_position(id) {
+            state.queue.remove(pos);
+        }
+        state.users.remove(&id);
+        if let Some(channel) = channel {
+            state.channels.remove(&channel);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn enqueue_rejects_duplicates() {
+        let store = MemoryStore::new();
+        let a = User::test(1);
+        assert!(store.enqueue(&a, 10).await.unwrap());
+        assert!(!store.enqueue(&a, 20).await.unwrap());
+        assert_eq!(store.waiting_users().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn waiting_users_are_in_join_order() {
+        let store = MemoryStore::new();
+        store.enqueue(&User::test(3), 30).await.unwrap();
+        store.enqueue(&User::test(1), 10).await.unwrap();
+        store.enqueue(&User::test(2), 20).await.unwrap();
+        let ids: Vec<u64> = store
+            .waiting_users()
+            .await
+            .unwrap()
+            .iter()
+            .map(|u| u.id.get())
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn pair_links_both_users_once() {
+        let store = MemoryStore::new();
+        let (a, b, c) = (User::test(1), User::test(2), User::test(3));
+        for (u, at) in [(&a, 1), (&b, 2), (&c, 3)] {
+            store.enqueue(u, at).await.unwrap();
+        }
+
+        assert!(store.pair(&a, &b, 0).await.unwrap());
+        // `b` was taken, a concurrent attempt to pair them with `c` loses.
+        assert!(!store.pair(&c, &b, 0).await.unwrap());
+
+        let a = store.load_user(a.id).await.unwrap().unwrap();
+        let b = store.load_user(b.id).await.unwrap().unwrap();
+        assert_eq!(a.partner, Some(b.id));
+        assert_eq!(a.partner_channel, Some(b.channel));
+        assert_eq!(b.partner, Some(a.id));
+        let waiting = store.waiting_users().await.unwrap();
+        assert_eq!(waiting.len(), 1);
+        assert_eq!(waiting[0].id, c.id);
+    }
+
+    #[tokio::test]
+    async fn dequeue_only_removes_waiting_users() {
+        let store = MemoryStore::new();
+        let (a, b) = (User::test(1), User::test(2));
+        store.enqueue(&a, 1).await.unwrap();
+        store.enqueue(&b, 2).await.unwrap();
+        store.pair(&a, &b, 0).await.unwrap();
+        assert!(!store.dequeue(&a).await.unwrap());
+
+        let c = User::test(3);
+        store.enqueue(&c, 3).await.unwrap();
+        assert!(store.dequeue(&c).await.unwrap());
+        assert!(store.load_user(c.id).await.unwrap().is_none());
+        assert!(store.user_by_channel(c.channel).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn unpair_tears_down_once() {
+        let store = MemoryStore::new();
+        let (a, b) = (User::test(1), User::test(2));
+        store.enqueue(&a, 1).await.unwrap();
+        store.enqueue(&b, 2).await.unwrap();
+        store.pair(&a, &b, 0).await.unwrap();
+
+        assert!(store.unpair(&a, &b).await.unwrap());
+        assert!(!store.unpair(&b, &a).await.unwrap());
+        assert!(store.user_by_channel(a.channel).await.unwrap().is_none());
+        assert!(store.all_users().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn skip_requeues_only_the_caller() {
+        let store = MemoryStore::new();
+        let (a, b) = (User::test(1).with_interests(&["rust"]), User::test(2));
+        store.enqueue(&a, 1).await.unwrap();
+        store.enqueue(&b, 2).await.unwrap();
+        store.pair(&a, &b, 0).await.unwrap();
+
+        let a = User {
+            wait_until: 50,
+            ..store.load_user(a.id).await.unwrap().unwrap()
+        };
+        assert!(store.skip(&a, &b, 5).await.unwrap());
+        assert!(!store.skip(&a, &b, 6).await.unwrap());
+
+        let waiting = store.waiting_users().await.unwrap();
+        assert_eq!(waiting.len(), 1);
+        assert_eq!(waiting[0].id, a.id);
+        assert_eq!(waiting[0].channel, a.channel);
+        assert_eq!(waiting[0].interests, vec!["rust"]);
+        assert_eq!(waiting[0].wait_until, 50);
+        assert!(store.load_user(b.id).await.unwrap().is_none());
+        assert!(store.user_by_channel(b.channel).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn touch_tracks_activity_and_resets_warnings() {
+        let store = MemoryStore::new();
+        let (a, b) = (User::test(1), User::test(2));
+        store.enqueue(&a, 1).await.unwrap();
+        store.enqueue(&b, 2).await.unwrap();
+        store.pair(&a, &b, 10).await.unwrap();
+        assert_eq!(
+            store.load_user(b.id).await.unwrap().unwrap().last_active,
+            10
+        );
+
+        store.mark_idle_warned(a.id).await.unwrap();
+        assert!(store.load_user(a.id).await.unwrap().unwrap().idle_warned);
+        store.touch(a.id, 20).await.unwrap();
+        let a = store.load_user(a.id).await.unwrap().unwrap();
+        assert_eq!(a.last_active, 20);
+        assert!(!a.idle_warned);
+
+        // Unknown users are not brought back by a late touch.
+        store.touch(UserId::new(3), 30).await.unwrap();
+        assert!(store.load_user(UserId::new(3)).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn requeue_and_forget_split_a_pair() {
+        let store = MemoryStore::new();
+        let (a, b) = (User::test(1), User::test(2));
+        store.enqueue(&a, 1).await.unwrap();
+        store.enqueue(&b, 2).await.unwrap();
+        store.pair(&a, &b, 0).await.unwrap();
+
+        store.forget(b.id, Some(b.channel)).await.unwrap();
+        assert!(store.requeue(&a, b.id, 5).await.unwrap());
+        assert!(!store.requeue(&a, b.id, 6).await.unwrap());
+
+        let a = store.user_by_channel(a.channel).await.unwrap().unwrap();
+        assert_eq!(a.partner, None);
+        assert_eq!(store.waiting_users().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn relay_links_work_both_ways_until_they_expire() {
+        let store = MemoryStore::new();
+        let source = (ChannelId::new(1), MessageId::new(10));
+        let mirror = (ChannelId::new(2), MessageId::new(20));
+        store.link_relay(source, mirror, 100).await.unwrap();
+
+        assert_eq!(store.mirror_of(source.1, 50).await.unwrap(), Some(mirror));
+        assert_eq!(store.source_of(mirror.1, 50).await.unwrap(), Some(source));
+        assert_eq!(store.mirror_of(mirror.1, 50).await.unwrap(), None);
+
+        assert_eq!(store.mirror_of(source.1, 100).await.unwrap(), None);
+        assert_eq!(store.source_of(mirror.1, 100).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn bans_cover_their_scope_until_they_expire() {
+        use crate::bans::{Ban, BanScope};
+
+        let store = MemoryStore::new();
+        let (user, guild) = (UserId::new(1), GuildId::new(5));
+        let ban = Ban {
+            user,
+            scope: BanScope::Guild(guild),
+            reason: None,
+            banned_by: UserId::new(9),
+            created_at: 0,
+            expires_at: Some(100),
+        };
+        store.add_ban(&ban).await.unwrap();
+
+        assert_eq!(
+            store.active_ban(user, Some(guild), 50).await.unwrap(),
+            Some(ban.clone())
+        );
+        assert_eq!(store.active_ban(user, None, 50).await.unwrap(), None);
+        assert_eq!(store.bans(BanScope::Global, 50).await.unwrap(), vec![]);
+        assert_eq!(
+            store.active_ban(user, Some(guild), 100).await.unwrap(),
+            None
+        );
+        assert!(store
+            .bans(BanScope::Guild(guild), 100)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let global = Ban {
+            scope: BanScope::Global,
+            expires_at: None,
+            ..ban
+        };
+        store.add_ban(&global).await.unwrap();
+        assert_eq!(
+            store.active_ban(user, Some(guild), 200).await.unwrap(),
+            Some(global)
+        );
+        assert!(store.remove_ban(user, BanScope::Global).await.unwrap());
+        assert!(!store.remove_ban(user, BanScope::Global).await.unwrap());
+        assert_eq!(store.active_ban(user, None, 200).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn reports_are_resolved_once() {
+        use crate::reports::{Outcome, Reason};
+
+        let store = MemoryStore::new();
+        let id = store.next_report_id().await.unwrap();
+        let report = Report {
+            id,
+            reporter: UserId::new(1),
+            reported: UserId::new(2),
+            reason: Reason::Spam,
+            thread: ChannelId::new(1001),
+            guild: GuildId::new(5),
+            evidence: "Stranger: buy now".to_string(),
+            created_at: 10,
+            resolution: None,
+        };
+        store.save_report(&report).await.unwrap();
+        assert_ne!(store.next_report_id().await.unwrap(), id);
+
+        let warn = Resolution {
+            outcome: Outcome::Warn,
+            moderator: UserId::new(9),
+            at: 20,
+        };
+        let ban = Resolution {
+            outcome: Outcome::Ban,
+            ..warn.clone()
+        };
+        assert!(store.resolve_report(id, &warn).await.unwrap());
+        assert!(!store.resolve_report(id, &ban).await.unwrap());
+        assert!(!store.resolve_report(id + 100, &ban).await.unwrap());
+        let stored = store.report(id).await.unwrap().unwrap();
+        assert_eq!(stored.resolution, Some(warn));
+        assert_eq!(stored.evidence, report.evidence);
+    }
+}
@@ -0,0 +1,271 @@
+//! Session state behind a [`SessionStore`], so matching and disconnect logic
+//! does not care whether it runs against Redis or a single in-process map.
+
+mod memory;
+mod redis_store;
+
+pub use memory::MemoryStore;
+pub use redis_store::RedisStore;
+
+use std::collections::HashSet;
+
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use serenity::async_trait;
+
+use crate::bans::{Ban, BanScope};
+use crate::matchmaking::Entry;
+use crate::media::MediaPolicy;
+use crate::reports::{Report, Resolution};
+use crate::strikes::Strike;
+use crate::StartOptions;
+
+#[derive(Debug, Clone)]
+pub struct User {
+    pub id: UserId,
+    pub channel: ChannelId,
+    /// The server `channel` lives in. Unknown for sessions stored before it
+    /// was recorded.
+    pub guild: Option<GuildId>,
+    /// The channel `channel` was started from, which relays to it go through.
+    /// Unknown for sessions stored before it was recorded.
+    pub lobby: Option<ChannelId>,
+    pub interests: Vec<String>,
+    pub partner: Option<UserId>,
+    pub partner_channel: Option<ChannelId>,
+    pub wait_until: u64,
+    /// How many seconds the user chose to hold out for a shared interest,
+    /// which every later search from the same thread uses again.
+    pub wait: u64,
+    pub anyone: bool,
+    /// When the user last did something (unix seconds): joined the queue, got
+    /// paired or sent a message.
+    pub last_active: u64,
+    /// Whether the pair was already warned about being idle since then.
+    pub idle_warned: bool,
+}
+
+impl User {
+    pub fn entry(&self) -> Entry {
+        Entry {
+            id: self.id.get(),
+            interests: self.interests.clone(),
+            wait_until: self.wait_until,
+            anyone: self.anyone,
+        }
+    }
+}
+
+/// A user record to start test fixtures from: waiting, without interests,
+/// in thread `id + 1000`.
+#[cfg(test)]
+impl User {
+    pub fn test(id: u64) -> User {
+        User {
+            id: UserId::new(id),
+            channel: ChannelId::new(id + 1000),
+            guild: None,
+            lobby: None,
+            interests: vec![],
+            partner: None,
+            partner_channel: None,
+            wait_until: 0,
+            wait: 0,
+            anyone: false,
+            last_active: 0,
+            idle_warned: false,
+        }
+    }
+
+    pub fn with_guild(mut self, guild: u64) -> User {
+        self.guild = Some(GuildId::new(guild));
+        self
+    }
+
+    pub fn with_interests(mut self, interests: &[&str]) -> User {
+        self.interests = interests.iter().map(|i| i.to_string()).collect();
+        self
+    }
+
+    /// Paired with the test user `partner`.
+    pub fn with_partner(mut self, partner: u64) -> User {
+        self.partner = Some(UserId::new(partner));
+        self.partner_channel = Some(ChannelId::new(partner + 1000));
+        self
+    }
+
+    pub fn with_wait_until(mut self, wait_until: u64) -> User {
+        self.wait_until = wait_until;
+        self
+    }
+
+    pub fn with_last_active(mut self, last_active: u64) -> User {
+        self.last_active = last_active;
+        self
+    }
+
+    pub fn with_idle_warned(mut self, idle_warned: bool) -> User {
+        self.idle_warned = idle_warned;
+        self
+    }
+}
+
+/// Where a message lives.
+pub type MessageRef = (ChannelId, MessageId);
+
+#[derive(Debug)]
+pub enum StoreError {
+    Redis(redis::RedisError),
+}
+
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Redis(e) => write!(f, "redis error: {e}"),
+        }
+    }
+}
+
+impl From<redis::RedisError> for StoreError {
+    fn from(error: redis::RedisError) -> Self {
+        StoreError::Redis(error)
+    }
+}
+
+pub type StoreResult<T> = Result<T, StoreError>;
+
+/// Every state transition is atomic: when two callers race for the same
+/// user, exactly one of them sees `true`.
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    async fn load_user(&self, id: UserId) -> StoreResult<Option<User>>;
+
+    async fn user_by_channel(&self, channel: ChannelId) -> StoreResult<Option<User>>;
+
+    /// Every user id the store has a record for, with the record itself if it
+    /// is still readable.
+    async fn all_users(&self) -> StoreResult<Vec<(UserId, Option<User>)>>;
+
+    /// Everyone currently waiting, longest-waiting first.
+    async fn waiting_users(&self) -> StoreResult<Vec<User>>;
+
+    /// A fresh number for naming a private thread without revealing its owner.
+    async fn next_chat_number(&self) -> StoreResult<u64>;
+
+    /// Adds `user` to the waiting queue. Returns `false` when they already
+    /// have a record, i.e. they are waiting or connected elsewhere.
+    async fn enqueue(&self, user: &User, queued_at: u64) -> StoreResult<bool>;
+
+    /// Pairs two waiting users, marking both active at `paired_at`. Returns
+    /// `false` if either of them left the queue since they were read, in which
+    /// case nothing is changed.
+    async fn pair(&self, a: &User, b: &User, paired_at: u64) -> StoreResult<bool>;
+
+    /// Removes a waiting user. Returns `false` if they were no longer waiting.
+    async fn dequeue(&self, user: &User) -> StoreResult<bool>;
+
+    /// Ends the session between `a` and `b`. Returns `false` if they were no
+    /// longer paired with each other, so only one caller tears it down.
+    async fn unpair(&self, a: &User, b: &User) -> StoreResult<bool>;
+
+    /// Puts a connected user back in the waiting queue after their partner
+    /// went away, with the `last_active` they carry. Returns `false` if they
+    /// were no longer paired with `partner`.
+    async fn requeue(&self, user: &User, partner: UserId, queued_at: u64) -> StoreResult<bool>;
+
+    /// Ends `user`'s session with `partner` from `user`'s side only: the
+    /// partner's records are dropped while `user` keeps their thread and goes
+    /// back in the queue with the `wait_until` and `last_active` they carry.
+    /// Returns `false` if the two were no longer paired with each other.
+    async fn skip(&self, user: &User, partner: &User, queued_at: u64) -> StoreResult<bool>;
+
+    /// Records activity by `id` at `at` and clears their idle warning. Does
+    /// nothing for users without a record.
+    async fn touch(&self, id: UserId, at: u64) -> StoreResult<()>;
+
+    /// Remembers that `id` was warned about being idle.
+    async fn mark_idle_warned(&self, id: UserId) -> StoreResult<()>;
+
+    /// Remembers that `mirror` is the relayed copy of `source` until
+    /// `expires_at` (unix seconds).
+    async fn link_relay(
+        &self,
+        source: MessageRef,
+        mirror: MessageRef,
+        expires_at: u64,
+    ) -> StoreResult<()>;
+
+    /// The relayed copy of the message `source`, if still remembered at `now`.
+    async fn mirror_of(&self, source: MessageId, now: u64) -> StoreResult<Option<MessageRef>>;
+
+    /// The message that `mirror` is a relayed copy of, if still remembered at
+    /// `now`.
+    async fn source_of(&self, mirror: MessageId, now: u64) -> StoreResult<Option<MessageRef>>;
+
+    /// The media policy set for `guild`, if any.
+    async fn media_policy(&self, guild: GuildId) -> StoreResult<Option<MediaPolicy>>;
+
+    async fn set_media_policy(&self, guild: GuildId, policy: &MediaPolicy) -> StoreResult<()>;
+
+    /// Whether `user` agreed to receive files from strangers. Kept across
+    /// sessions.
+    async fn media_opted_in(&self, user: UserId) -> StoreResult<bool>;
+
+    async fn set_media_opt_in(&self, user: UserId, opted_in: bool) -> StoreResult<()>;
+
+    /// Remembers the options `user` last searched for a partner with. Kept
+    /// across sessions.
+    async fn save_search(&self, user: UserId, options: &StartOptions) -> StoreResult<()>;
+
+    async fn last_search(&self, user: UserId) -> StoreResult<Option<StartOptions>>;
+
+    /// A fresh number to file a report under.
+    async fn next_report_id(&self) -> StoreResult<u64>;
+
+    async fn save_report(&self, report: &Report) -> StoreResult<()>;
+
+    async fn report(&self, id: u64) -> StoreResult<Option<Report>>;
+
+    /// Records how report `id` was handled. Returns `false` if it was already
+    /// handled or does not exist, so only the first moderator's decision
+    /// counts.
+    async fn resolve_report(&self, id: u64, resolution: &Resolution) -> StoreResult<bool>;
+
+    /// Bans `ban.user` in `ban.scope`, replacing any earlier ban there.
+    async fn add_ban(&self, ban: &Ban) -> StoreResult<()>;
+
+    /// Lifts the ban of `user` in `scope`. Returns `false` if there was none.
+    async fn remove_ban(&self, user: UserId, scope: BanScope) -> StoreResult<bool>;
+
+    /// Every ban in `scope` still active at `now`.
+    async fn bans(&self, scope: BanScope, now: u64) -> StoreResult<Vec<Ban>>;
+
+    /// The ban keeping `user` from chatting in `guild` at `now`, global bans
+    /// first.
+    async fn active_ban(
+        &self,
+        user: UserId,
+        guild: Option<GuildId>,
+        now: u64,
+    ) -> StoreResult<Option<Ban>>;
+
+    /// Adds a strike against `strike.user`. Returns how many they have now.
+    async fn add_strike(&self, strike: &Strike) -> StoreResult<usize>;
+
+    /// Every strike against `user`, oldest first.
+    async fn strikes(&self, user: UserId) -> StoreResult<Vec<Strike>>;
+
+    /// Wipes `user`'s strikes. Returns how many there were.
+    async fn clear_strikes(&self, user: UserId) -> StoreResult<usize>;
+
+    /// Adds `blocked` to `user`'s blocklist. Returns `false` if they were on
+    /// it already.
+    async fn block(&self, user: UserId, blocked: UserId) -> StoreResult<bool>;
+
+    /// Everyone `user` blocked or was blocked by, who they must never be
+    /// paired with.
+    async fn blocks_involving(&self, user: UserId) -> StoreResult<HashSet<UserId>>;
+
+    /// Drops everything belonging to a user regardless of their state. Used
+    /// for records whose thread no longer exists.
+    async fn forget(&self, id: UserId, channel: Option<ChannelId>) -> StoreResult<()>;
+}
@@ -0,0 +1,837 @@
+//! Redis-backed [`SessionStore`].
+//!
+//! Every user the bot knows about is a hash at `omeg:user:{id}`, the waiting
+//! queue is the sorted set `omeg:queue` scored by join time, and
+//! `omeg:channel:{id}` maps a private thread back to its owner. Relayed
+//! messages are linked both ways by `omeg:mirror:{source}` and
+//! `omeg:source:{mirror}`, each holding `{channel}:{message}`. Per-server
+//! media policies are hashes at `omeg:media:{guild}` and users who opted in
+//! to receiving media are in the set `omeg:media_opt_in`. The options each
+//! user last searched with are hashes at `omeg:search:{user}`. Reports are
+//! hashes at `omeg:report:{id}`, numbered by `omeg:report_counter`. Bans are
+//! hashes at `omeg:ban:{scope}:{user}`, where the scope is `global` or a
+//! server id, that expire along with the ban; the set `omeg:bans:{scope}`
+//! lists who is banned there. Strikes are lists at `omeg:strikes:{user}` holding
+//! `{at}:{moderator}:{reason}`, with an empty moderator for automatic ones.
+//! Blocks are kept both ways, in the sets `omeg:blocks:{user}` (who `user`
+//! blocked) and `omeg:blocked_by:{user}`. State
+//! transitions that touch more than one key run as Lua scripts so concurrent
+//! interactions can never observe or produce a half-applied pairing.
+
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroU64;
+use std::sync::LazyLock;
+
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Script};
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use serenity::async_trait;
+
+use super::{MessageRef, SessionStore, StoreResult, User};
+use crate::bans::{Ban, BanScope};
+use crate::media::{parse_types, MediaPolicy};
+use crate::reports::{Outcome, Reason, Report, Resolution};
+use crate::strikes::Strike;
+use crate::{StartOptions, DEFAULT_WAIT_SECS};
+
+const QUEUE_KEY: &str = "omeg:queue";
+const USERS_KEY: &str = "omeg:users";
+const CHAT_COUNTER_KEY: &str = "omeg:chat_counter";
+const MEDIA_OPT_IN_KEY: &str = "omeg:media_opt_in";
+const REPORT_COUNTER_KEY: &str = "omeg:report_counter";
+
+pub struct RedisStore {
+    con: ConnectionManager,
+}
+
+impl RedisStore {
+    pub fn new(con: ConnectionManager) -> RedisStore {
+        RedisStore { con }
+    }
+}
+
+fn user_key(id: UserId) -> String {
+    format!("omeg:user:{id}")
+}
+
+fn channel_key(id: ChannelId) -> String {
+    format!("omeg:channel:{id}")
+}
+
+fn mirror_key(source: MessageId) -> String {
+    format!("omeg:mirror:{source}")
+}
+
+fn source_key(mirror: MessageId) -> String {
+    format!("omeg:source:{mirror}")
+}
+
+fn media_key(guild: GuildId) -> String {
+    format!("omeg:media:{guild}")
+}
+
+fn search_key(user: UserId) -> String {
+    format!("omeg:search:{user}")
+}
+
+fn search_fields(options: &StartOptions) -> Vec<(&'static str, String)> {
+    vec![
+        ("interests", options.interests.join(",")),
+        ("wait", options.wait.to_string()),
+        ("anyone", (options.anyone as u8).to_string()),
+    ]
+}
+
+fn search_from_fields(fields: &HashMap<String, String>) -> Option<StartOptions> {
+    let field = |name: &str| fields.get(name).map(String::as_str);
+    Some(StartOptions {
+        interests: field("interests")?
+            .split(',')
+            .filter(|i| !i.is_empty())
+            .map(str::to_string)
+            .collect(),
+        wait: field("wait")?.parse().ok()?,
+        anyone: field("anyone") == Some("1"),
+    })
+}
+
+fn media_fields(policy: &MediaPolicy) -> Vec<(&'static str, String)> {
+    vec![
+        ("enabled", (policy.enabled as u8).to_string()),
+        ("max_bytes", policy.max_bytes.to_string()),
+        ("image_only", (policy.image_only as u8).to_string()),
+        ("allowed_types", policy.allowed_types.join(",")),
+    ]
+}
+
+fn media_from_fields(fields: &HashMap<String, String>) -> Option<MediaPolicy> {
+    if fields.is_empty() {
+        return None;
+    }
+    let field = |name: &str| fields.get(name).map(String::as_str);
+    let defaults = MediaPolicy::default();
+    Some(MediaPolicy {
+        enabled: field("enabled") == Some("1"),
+        max_bytes: field("max_bytes")
+            .and_then(|b| b.parse().ok())
+            .unwrap_or(defaults.max_bytes),
+        image_only: field("image_only") == Some("1"),
+        allowed_types: parse_types(field("allowed_types").unwrap_or_default()),
+    })
+}
+
+fn report_key(id: u64) -> String {
+    format!("omeg:report:{id}")
+}
+
+fn report_fields(report: &Report) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("id", report.id.to_string()),
+        ("reporter", report.reporter.to_string()),
+        ("reported", report.reported.to_string()),
+        ("reason", report.reason.as_str().to_string()),
+        ("thread", report.thread.to_string()),
+        ("guild", report.guild.to_string()),
+        ("evidence", report.evidence.clone()),
+        ("created_at", report.created_at.to_string()),
+    ];
+    if let Some(resolution) = &report.resolution {
+        fields.extend(resolution_fields(resolution));
+    }
+    fields
+}
+
+fn resolution_fields(resolution: &Resolution) -> Vec<(&'static str, String)> {
+    vec![
+        ("outcome", resolution.outcome.as_str().to_string()),
+        ("moderator", resolution.moderator.to_string()),
+        ("resolved_at", resolution.at.to_string()),
+    ]
+}
+
+fn report_from_fields(fields: &HashMap<String, String>) -> Option<Report> {
+    let field = |name: &str| fields.get(name).map(String::as_str);
+    let resolution = match (field("outcome"), field("moderator")) {
+        (Some(outcome), Some(moderator)) => Some(Resolution {
+            outcome: Outcome::parse(outcome)?,
+            moderator: parse_id(moderator)?,
+            at: field("resolved_at")?.parse().ok()?,
+        }),
+        _ => None,
+    };
+    Some(Report {
+        id: field("id")?.parse().ok()?,
+        reporter: parse_id(field("reporter")?)?,
+        reported: parse_id(field("reported")?)?,
+        reason: Reason::parse(field("reason")?)?,
+        thread: parse_id(field("thread")?)?,
+        guild: parse_id(field("guild")?)?,
+        evidence: field("evidence").unwrap_or_default().to_string(),
+        created_at: field("created_at")?.parse().ok()?,
+        resolution,
+    })
+}
+
+fn scope_name(scope: BanScope) -> String {
+    match scope {
+        BanScope::Global => "global".to_string(),
+        BanScope::Guild(guild) => guild.to_string(),
+    }
+}
+
+fn ban_key(scope: BanScope, user: UserId) -> String {
+    format!("omeg:ban:{}:{user}", scope_name(scope))
+}
+
+fn bans_key(scope: BanScope) -> String {
+    format!("omeg:bans:{}", scope_name(scope))
+}
+
+fn ban_fields(ban: &Ban) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("user", ban.user.to_string()),
+        ("banned_by", ban.banned_by.to_string()),
+        ("created_at", ban.created_at.to_string()),
+    ];
+    if let Some(reason) = &ban.reason {
+        fields.push(("reason", reason.clone()));
+    }
+    if let Some(expires_at) = ban.expires_at {
+        fields.push(("expires_at", expires_at.to_string()));
+    }
+    fields
+}
+
+fn ban_from_fields(scope: BanScope, fields: &HashMap<String, String>) -> Option<Ban> {
+    let field = |name: &str| fields.get(name).map(String::as_str);
+    Some(Ban {
+        user: parse_id(field("user")?)?,
+        scope,
+        reason: field("reason").map(str::to_string),
+        banned_by: parse_id(field("banned_by")?)?,
+        created_at: field("created_at")?.parse().ok()?,
+        expires_at: field("expires_at").and_then(|at| at.parse().ok()),
+    })
+}
+
+fn blocks_key(user: UserId) -> String {
+    format!("omeg:blocks:{user}")
+}
+
+fn blocked_by_key(user: UserId) -> String {
+    format!("omeg:blocked_by:{user}")
+}
+
+fn strikes_key(user: UserId) -> String {
+    format!("omeg:strikes:{user}")
+}
+
+fn strike_value(strike: &Strike) -> String {
+    let issued_by = strike.issued_by.map(|m| m.to_string()).unwrap_or_default();
+    // The reason goes last since it may contain the separator.
+    format!("{}:{issued_by}:{}", strike.at, strike.reason)
+}
+
+fn parse_strike(user: UserId, value: &str) -> Option<Strike> {
+    let mut parts = value.splitn(3, ':');
+    let at = parts.next()?.parse().ok()?;
+    let issued_by = parts.next()?;
+    Some(Strike {
+        user,
+        reason: parts.next()?.to_string(),
+        issued_by: parse_id(issued_by),
+        at,
+    })
+}
+
+fn message_ref_value((channel, message): MessageRef) -> String {
+    format!("{channel}:{message}")
+}
+
+fn parse_message_ref(value: &str) -> Option<MessageRef> {
+    let (channel, message) = value.split_once(':')?;
+    Some((parse_id(channel)?, parse_id(message)?))
+}
+
+fn parse_id<T: From<NonZeroU64>>(value: &str) -> Option<T> {
+    value.parse::<NonZeroU64>().ok().map(T::from)
+}
+
+impl User {
+    fn to_fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("id", self.id.to_string()),
+            ("channel", self.channel.to_string()),
+            // Interests come from a comma separated option, so they never
+            // contain a comma themselves.
+            ("interests", self.interests.join(",")),
+            ("wait_until", self.wait_until.to_string()),
+            ("wait", self.wait.to_string()),
+            ("anyone", (self.anyone as u8).to_string()),
+            ("last_active", self.last_active.to_string()),
+            ("idle_warned", (self.idle_warned as u8).to_string()),
+        ];
+        if let Some(guild) = self.guild {
+            fields.push(("guild", guild.to_string()));
+        }
+        if let Some(lobby) = self.lobby {
+            fields.push(("lobby", lobby.to_string()));
+        }
+        if let Some(partner) = self.partner {
+            fields.push(("partner", partner.to_string()));
+        }
+        if let Some(partner_channel) = self.partner_channel {
+            fields.push(("partner_channel", partner_channel.to_string()));
+        }
+        fields
+    }
+
+    fn from_fields(fields: &HashMap<String, String>) -> Option<User> {
+        let field = |name: &str| fields.get(name).map(String::as_str);
+        Some(User {
+            id: parse_id(field("id")?)?,
+            channel: parse_id(field("channel")?)?,
+            guild: field("guild").and_then(parse_id),
+            lobby: field("lobby").and_then(parse_id),
+            interests: field("interests")
+                .unwrap_or_default()
+                .split(',')
+                .filter(|i| !i.is_empty())
+                .map(str::to_string)
+                .collect(),
+            partner: field("partner").and_then(parse_id),
+            partner_channel: field("partner_channel").and_then(parse_id),
+            wait_until: field("wait_until")
+                .and_then(|w| w.parse().ok())
+                .unwrap_or(0),
+            wait: field("wait")
+                .and_then(|w| w.parse().ok())
+                .unwrap_or(DEFAULT_WAIT_SECS),
+            anyone: field("anyone") == Some("1"),
+            last_active: field("last_active")
+                .and_then(|t| t.parse().ok())
+                .unwrap_or(0),
+            idle_warned: field("idle_warned") == Some("1"),
+        })
+    }
+}
+
+static ENQUEUE: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        if redis.call('EXISTS', KEYS[1]) == 1 then
+            return 0
+        end
+        redis.call('HSET', KEYS[1], unpack(ARGV, 3))
+        redis.call('ZADD', KEYS[2], ARGV[2], ARGV[1])
+        redis.call('SET', KEYS[3], ARGV[1])
+        redis.call('SADD', KEYS[4], ARGV[1])
+        return 1
+        ",
+    )
+});
+
+static PAIR: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        if not redis.call('ZSCORE', KEYS[3], ARGV[1]) or not redis.call('ZSCORE', KEYS[3], ARGV[2]) then
+            return 0
+        end
+        local a_channel = redis.call('HGET', KEYS[1], 'channel')
+        local b_channel = redis.call('HGET', KEYS[2], 'channel')
+        if not a_channel or not b_channel then
+            return 0
+        end
+        redis.call('ZREM', KEYS[3], ARGV[1], ARGV[2])
+        redis.call('HSET', KEYS[1], 'partner', ARGV[2], 'partner_channel', b_channel, 'last_active', ARGV[3], 'idle_warned', '0')
+        redis.call('HSET', KEYS[2], 'partner', ARGV[1], 'partner_channel', a_channel, 'last_active', ARGV[3], 'idle_warned', '0')
+        return 1
+        ",
+    )
+});
+
+static DEQUEUE: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        if redis.call('ZREM', KEYS[2], ARGV[1]) == 0 then
+            return 0
+        end
+        redis.call('DEL', KEYS[1], KEYS[3])
+        redis.call('SREM', KEYS[4], ARGV[1])
+        return 1
+        ",
+    )
+});
+
+static REQUEUE: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        if redis.call('HGET', KEYS[1], 'partner') ~= ARGV[2] then
+            return 0
+        end
+        redis.call('HDEL', KEYS[1], 'partner', 'partner_channel')
+        redis.call('HSET', KEYS[1], 'last_active', ARGV[4], 'idle_warned', '0')
+        redis.call('ZADD', KEYS[2], ARGV[3], ARGV[1])
+        return 1
+        ",
+    )
+});
+
+static FORGET: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        redis.call('ZREM', KEYS[1], ARGV[1])
+        redis.call('SREM', KEYS[2], ARGV[1])
+        redis.call('DEL', unpack(KEYS, 3))
+        return 1
+        ",
+    )
+});
+
+static UNPAIR: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        if redis.call('HGET', KEYS[1], 'partner') ~= ARGV[2] or redis.call('HGET', KEYS[2], 'partner') ~= ARGV[1] then
+            return 0
+        end
+        redis.call('DEL', KEYS[1], KEYS[2], KEYS[3], KEYS[4])
+        redis.call('SREM', KEYS[5], ARGV[1], ARGV[2])
+        return 1
+        ",
+    )
+});
+
+static SKIP: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        if redis.call('HGET', KEYS[1], 'partner') ~= ARGV[2] or redis.call('HGET', KEYS[2], 'partner') ~= ARGV[1] then
+            return 0
+        end
+        redis.call('DEL', KEYS[2], KEYS[3])
+        redis.call('SREM', KEYS[5], ARGV[2])
+        redis.call('HDEL', KEYS[1], 'partner', 'partner_channel')
+        redis.call('HSET', KEYS[1], 'wait_until', ARGV[4], 'last_active', ARGV[5], 'idle_warned', '0')
+        redis.call('ZADD', KEYS[4], ARGV[3], ARGV[1])
+        return 1
+        ",
+    )
+});
+
+/// Sets fields on a user hash only if the user still exists, so a late update
+/// never resurrects a half-empty record.
+static UPDATE_EXISTING: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        if redis.call('EXISTS', KEYS[1]) == 0 then
+            return 0
+        end
+        redis.call('HSET', KEYS[1], unpack(ARGV))
+        return 1
+        ",
+    )
+});
+
+/// Records a report's resolution unless it already has one.
+static RESOLVE_REPORT: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        if redis.call('EXISTS', KEYS[1]) == 0 or redis.call('HEXISTS', KEYS[1], 'outcome') == 1 then
+            return 0
+        end
+        redis.call('HSET', KEYS[1], unpack(ARGV))
+        return 1
+        ",
+    )
+});
+
+#[async_trait]
+impl SessionStore for RedisStore {
+    async fn load_user(&self, id: UserId) -> StoreResult<Option<User>> {
+        let fields: HashMap<String, String> = self.con.clone().hgetall(user_key(id)).await?;
+        Ok(User::from_fields(&fields))
+    }
+
+    async fn user_by_channel(&self, channel: ChannelId) -> StoreResult<Option<User>> {
+        let owner: Option<String> = self.con.clone().get(channel_key(channel)).await?;
+        match owner.as_deref().and_then(parse_id) {
+            Some(id) => self.load_user(id).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn all_users(&self) -> StoreResult<Vec<(UserId, Option<User>)>> {
+        let mut con = self.con.clone();
+        let ids: Vec<String> = con.smembers(USERS_KEY).await?;
+        let ids: Vec<UserId> = ids.iter().filter_map(|id| parse_id(id)).collect();
+        let mut pipe = redis::pipe();
+        for id in &ids {
+            pipe.hgetall(user_key(*id));
+        }
+        let users: Vec<HashMap<String, String>> = pipe.query_async(&mut con).await?;
+        Ok(ids
+            .into_iter()
+            .zip(users.iter().map(User::from_fields))
+            .collect())
+    }
+
+    async fn waiting_users(&self) -> StoreResult<Vec<User>> {
+        let mut con = self.con.clone();
+        let ids: Vec<String> = con.zrange(QUEUE_KEY, 0, -1).await?;
+        let mut pipe = redis::pipe();
+        for id in ids.iter().filter_map(|id| parse_id(id)) {
+            pipe.hgetall(user_key(id));
+        }
+        let users: Vec<HashMap<String, String>> = pipe.query_async(&mut con).await?;
+        Ok(users.iter().filter_map(User::from_fields).collect())
+    }
+
+    async fn next_chat_number(&self) -> StoreResult<u64> {
+        Ok(self.con.clone().incr(CHAT_COUNTER_KEY, 1).await?)
+    }
+
+    async fn enqueue(&self, user: &User, queued_at: u64) -> StoreResult<bool> {
+        let mut invocation = ENQUEUE.prepare_invoke();
+        invocation
+            .key(user_key(user.id))
+            .key(QUEUE_KEY)
+            .key(channel_key(user.channel))
+            .key(USERS_KEY)
+            .arg(user.id.to_string())
+            .arg(queued_at);
+        for (name, value) in user.to_fields() {
+            invocation.arg(name).arg(value);
+        }
+        let added: i32 = invocation.invoke_async(&mut self.con.clone()).await?;
+        Ok(added == 1)
+    }
+
+    async fn pair(&self, a: &User, b: &User, paired_at: u64) -> StoreResult<bool> {
+        let paired: i32 = PAIR
+            .key(user_key(a.id))
+            .key(user_key(b.id))
+            .key(QUEUE_KEY)
+            .arg(a.id.to_string())
+            .arg(b.id.to_string())
+            .arg(paired_at)
+            .invoke_async(&mut self.con.clone())
+            .await?;
+        Ok(paired == 1)
+    }
+
+    async fn dequeue(&self, user: &User) -> StoreResult<bool> {
+        let removed: i32 = DEQUEUE
+            .key(user_key(user.id))
+            .key(QUEUE_KEY)
+            .key(channel_key(user.channel))
+            .key(USERS_KEY)
+            .arg(user.id.to_string())
+            .invoke_async(&mut self.con.clone())
+            .await?;
+        Ok(removed == 1)
+    }
+
+    async fn unpair(&self, a: &User, b: &User) -> StoreResult<bool> {
+        let removed: i32 = UNPAIR
+            .key(user_key(a.id))
+            .key(user_key(b.id))
+            .key(channel_key(a.channel))
+            .key(channel_key(b.channel))
+            .key(USERS_KEY)
+            .arg(a.id.to_string())
+            .arg(b.id.to_string())
+            .invoke_async(&mut self.con.clone())
+            .await?;
+        Ok(removed == 1)
+    }
+
+    async fn requeue(&self, user: &User, partner: UserId, queued_at: u64) -> StoreResult<bool> {
+        let requeued: i32 = REQUEUE
+            .key(user_key(user.id))
+            .key(QUEUE_KEY)
+            .arg(user.id.to_string())
+            .arg(partner.to_string())
+            .arg(queued_at)
+            .arg(user.last_active)
+            .invoke_async(&mut self.con.clone())
+            .await?;
+        Ok(requeued == 1)
+    }
+
+    async fn skip(&self, user: &User, partner: &User, queued_at: u64) -> StoreResult<bool> {
+        let skipped: i32 = SKIP
+            .key(user_key(user.id))
+            .key(user_key(partner.id))
+            .key(channel_key(partner.channel))
+            .key(QUEUE_KEY)
+            .key(USERS_KEY)
+            .arg(user.id.to_string())
+            .arg(partner.id.to_string())
+            .arg(queued_at)
+            .arg(user.wait_until)
+            .arg(user.last_active)
+            .invoke_async(&mut self.con.clone())
+            .await?;
+        Ok(skipped == 1)
+    }
+
+    async fn touch(&self, id: UserId, at: u64) -> StoreResult<()> {
+        let _: i32 = UPDATE_EXISTING
+            .key(user_key(id))
+            .arg("last_active")
+            .arg(at)
+            .arg("idle_warned")
+            .arg("0")
+            .invoke_async(&mut self.con.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn mark_idle_warned(&self, id: UserId) -> StoreResult<()> {
+        let _: i32 = UPDATE_EXISTING
+            .key(user_key(id))
+            .arg("idle_warned")
+            .arg("1")
+            .invoke_async(&mut self.con.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn link_relay(
+        &self,
+        source: MessageRef,
+        mirror: MessageRef,
+        expires_at: u64,
+    ) -> StoreResult<()> {
+        // Links expire on their own, so no cleanup is needed when the
+        // session ends.
+        redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(mirror_key(source.1))
+            .arg(message_ref_value(mirror))
+            .arg("EXAT")
+            .arg(expires_at)
+            .ignore()
+            .cmd("SET")
+            .arg(source_key(mirror.1))
+            .arg(message_ref_value(source))
+            .arg("EXAT")
+            .arg(expires_at)
+            .ignore()
+            .query_async::<_, ()>(&mut self.con.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn mirror_of(&self, source: MessageId, _now: u64) -> StoreResult<Option<MessageRef>> {
+        let value: Option<String> = self.con.clone().get(mirror_key(source)).await?;
+        Ok(value.as_deref().and_then(parse_message_ref))
+    }
+
+    async fn source_of(&self, mirror: MessageId, _now: u64) -> StoreResult<Option<MessageRef>> {
+        let value: Option<String> = self.con.clone().get(source_key(mirror)).await?;
+        Ok(value.as_deref().and_then(parse_message_ref))
+    }
+
+    async fn media_policy(&self, guild: GuildId) -> StoreResult<Option<MediaPolicy>> {
+        let fields: HashMap<String, String> = self.con.clone().hgetall(media_key(guild)).await?;
+        Ok(media_from_fields(&fields))
+    }
+
+    async fn set_media_policy(&self, guild: GuildId, policy: &MediaPolicy) -> StoreResult<()> {
+        let _: () = self
+            .con
+            .clone()
+            .hset_multiple(media_key(guild), &media_fields(policy))
+            .await?;
+        Ok(())
+    }
+
+    async fn media_opted_in(&self, user: UserId) -> StoreResult<bool> {
+        Ok(self
+            .con
+            .clone()
+            .sismember(MEDIA_OPT_IN_KEY, user.to_string())
+            .await?)
+    }
+
+    async fn set_media_opt_in(&self, user: UserId, opted_in: bool) -> StoreResult<()> {
+        let mut con = self.con.clone();
+        let _: i32 = if opted_in {
+            con.sadd(MEDIA_OPT_IN_KEY, user.to_string()).await?
+        } else {
+            con.srem(MEDIA_OPT_IN_KEY, user.to_string()).await?
+        };
+        Ok(())
+    }
+
+    async fn save_search(&self, user: UserId, options: &StartOptions) -> StoreResult<()> {
+        let _: () = self
+            .con
+            .clone()
+            .hset_multiple(search_key(user), &search_fields(options))
+            .await?;
+        Ok(())
+    }
+
+    async fn last_search(&self, user: UserId) -> StoreResult<Option<StartOptions>> {
+        let fields: HashMap<String, String> = self.con.clone().hgetall(search_key(user)).await?;
+        Ok(search_from_fields(&fields))
+    }
+
+    async fn next_report_id(&self) -> StoreResult<u64> {
+        Ok(self.con.clone().incr(REPORT_COUNTER_KEY, 1).await?)
+    }
+
+    async fn save_report(&self, report: &Report) -> StoreResult<()> {
+        let _: () = self
+            .con
+            .clone()
+            .hset_multiple(report_key(report.id), &report_fields(report))
+            .await?;
+        Ok(())
+    }
+
+    async fn report(&self, id: u64) -> StoreResult<Option<Report>> {
+        let fields: HashMap<String, String> = self.con.clone().hgetall(report_key(id)).await?;
+        Ok(report_from_fields(&fields))
+    }
+
+    async fn resolve_report(&self, id: u64, resolution: &Resolution) -> StoreResult<bool> {
+        let mut invocation = RESOLVE_REPORT.prepare_invoke();
+        invocation.key(report_key(id));
+        for (name, value) in resolution_fields(resolution) {
+            invocation.arg(name).arg(value);
+        }
+        let resolved: i32 = invocation.invoke_async(&mut self.con.clone()).await?;
+        Ok(resolved == 1)
+    }
+
+    async fn add_ban(&self, ban: &Ban) -> StoreResult<()> {
+        let key = ban_key(ban.scope, ban.user);
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .del(&key)
+            .ignore()
+            .hset_multiple(&key, &ban_fields(ban))
+            .ignore()
+            .sadd(bans_key(ban.scope), ban.user.to_string())
+            .ignore();
+        if let Some(expires_at) = ban.expires_at {
+            pipe.expire_at(&key, expires_at as i64).ignore();
+        }
+        pipe.query_async::<_, ()>(&mut self.con.clone()).await?;
+        Ok(())
+    }
+
+    async fn remove_ban(&self, user: UserId, scope: BanScope) -> StoreResult<bool> {
+        let (removed, _): (i32, i32) = redis::pipe()
+            .atomic()
+            .del(ban_key(scope, user))
+            .srem(bans_key(scope), user.to_string())
+            .query_async(&mut self.con.clone())
+            .await?;
+        Ok(removed == 1)
+    }
+
+    async fn bans(&self, scope: BanScope, now: u64) -> StoreResult<Vec<Ban>> {
+        let mut con = self.con.clone();
+        let ids: Vec<String> = con.smembers(bans_key(scope)).await?;
+        let ids: Vec<UserId> = ids.iter().filter_map(|id| parse_id(id)).collect();
+        let mut pipe = redis::pipe();
+        for id in &ids {
+            pipe.hgetall(ban_key(scope, *id));
+        }
+        let fields: Vec<HashMap<String, String>> = pipe.query_async(&mut con).await?;
+        let mut bans = vec![];
+        let mut expired = vec![];
+        for (id, fields) in ids.into_iter().zip(&fields) {
+            match ban_from_fields(scope, fields).filter(|b| b.is_active(now)) {
+                Some(ban) => bans.push(ban),
+                None => expired.push(id.to_string()),
+            }
+        }
+        // Expired bans leave their id behind in the set.
+        if !expired.is_empty() {
+            let _: i32 = con.srem(bans_key(scope), expired).await?;
+        }
+        bans.sort_by_key(|b| b.created_at);
+        Ok(bans)
+    }
+
+    async fn active_ban(
+        &self,
+        user: UserId,
+        guild: Option<GuildId>,
+        now: u64,
+    ) -> StoreResult<Option<Ban>> {
+        let mut con = self.con.clone();
+        let scopes = std::iter::once(BanScope::Global).chain(guild.map(BanScope::Guild));
+        for scope in scopes {
+            let fields: HashMap<String, String> = con.hgetall(ban_key(scope, user)).await?;
+            if let Some(ban) = ban_from_fields(scope, &fields).filter(|b| b.is_active(now)) {
+                return Ok(Some(ban));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn add_strike(&self, strike: &Strike) -> StoreResult<usize> {
+        Ok(self
+            .con
+            .clone()
+            .rpush(strikes_key(strike.user), strike_value(strike))
+            .await?)
+    }
+
+    async fn strikes(&self, user: UserId) -> StoreResult<Vec<Strike>> {
+        let values: Vec<String> = self.con.clone().lrange(strikes_key(user), 0, -1).await?;
+        Ok(values
+            .iter()
+            .filter_map(|v| parse_strike(user, v))
+            .collect())
+    }
+
+    async fn clear_strikes(&self, user: UserId) -> StoreResult<usize> {
+        let (count, _): (usize, i32) = redis::pipe()
+            .atomic()
+            .llen(strikes_key(user))
+            .del(strikes_key(user))
+            .query_async(&mut self.con.clone())
+            .await?;
+        Ok(count)
+    }
+
+    async fn block(&self, user: UserId, blocked: UserId) -> StoreResult<bool> {
+        let (added, _): (i32, i32) = redis::pipe()
+            .atomic()
+            .sadd(blocks_key(user), blocked.to_string())
+            .sadd(blocked_by_key(blocked), user.to_string())
+            .query_async(&mut self.con.clone())
+            .await?;
+        Ok(added == 1)
+    }
+
+    async fn blocks_involving(&self, user: UserId) -> StoreResult<HashSet<UserId>> {
+        let ids: Vec<String> = self
+            .con
+            .clone()
+            .sunion(&[blocks_key(user), blocked_by_key(user)])
+            .await?;
+        Ok(ids.iter().filter_map(|id| parse_id(id)).collect())
+    }
+
+    async fn forget(&self, id: UserId, channel: Option<ChannelId>) -> StoreResult<()> {
+        let mut invocation = FORGET.prepare_invoke();
+        invocation
+            .key(QUEUE_KEY)
+            .key(USERS_KEY)
+            .key(user_key(id))
+            .arg(id.to_string());
+        if let Some(channel) = channel {
+            invocation.key(channel_key(channel));
+        }
+        let _: i32 = invocation.invoke_async(&mut self.con.clone()).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+//! Graduated moderation. Every warning from a moderator and every invite or
+//! blocklisted link the filter took out is a strike against the sender, and
+//! enough strikes first earn a warning, then a cooldown and finally a
+//! permanent ban from chatting, per the [`StrikePolicy`]. That ban is an
+//! ordinary [`Ban`], listed and lifted like any other.
+
+use std::time::Duration;
+
+use serenity::all::{Context, UserId};
+
+use crate::bans::{self, Ban, BanScope};
+use crate::config::get_config;
+use crate::error::GenericError;
+use crate::{expel, get_sessions, notify_user};
+
+const DEFAULT_COOLDOWN_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Strike {
+    pub user: UserId,
+    pub reason: String,
+    /// The moderator who gave it, or `None` for automatic strikes.
+    pub issued_by: Option<UserId>,
+    /// When it was given (unix seconds).
+    pub at: u64,
+}
+
+/// How many strikes lead to what. A threshold of 0 turns that step off.
+#[derive(Debug, Clone)]
+pub struct StrikePolicy {
+    pub warn_at: usize,
+    pub cooldown_at: usize,
+    /// How long someone at `cooldown_at` strikes or more may not chat after
+    /// their latest strike.
+    pub cooldown: Duration,
+    pub ban_at: usize,
+}
+
+impl Default for StrikePolicy {
+    fn default() -> StrikePolicy {
+        StrikePolicy {
+            warn_at: 1,
+            cooldown_at: 3,
+            cooldown: Duration::from_secs(DEFAULT_COOLDOWN_SECS),
+            ban_at: 5,
+        }
+    }
+}
+
+/// What reaching a number of strikes sets off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Escalation {
+    Warn,
+    Cooldown,
+    Ban,
+}
+
+/// What keeps someone with strikes from chatting right now. Reaching the ban
+/// threshold imposes a [`Ban`] instead, which is checked on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Restriction {
+    /// Until the given time (unix seconds).
+    Cooldown(u64),
+}
+
+impl Restriction {
+    /// How the restriction reads to a moderator.
+    pub fn describe(self) -> String {
+        match self {
+            Restriction::Cooldown(until) => format!("on cooldown until <t:{until}:f>"),
+        }
+    }
+
+    /// What the user is told when they try to chat.
+    pub fn refusal(self, strikes: usize) -> String {
+        match self {
+            Restriction::Cooldown(until) => format!(
+                "You have {strikes} strikes and cannot chat with strangers until <t:{until}:f>."
+            ),
+        }
+    }
+}
+
+impl StrikePolicy {
+    /// The step reached with the `count`th strike, if any. Every strike from
+    /// `ban_at` on bans, so once a strike ban is lifted the ledger is kept and
+    /// the next strike bans again.
+    pub fn escalation(&self, count: usize) -> Option<Escalation> {
+        let reached = |threshold: usize| threshold != 0 && count == threshold;
+        if self.ban_at != 0 && count >= self.ban_at {
+            Some(Escalation::Ban)
+        } else if reached(self.cooldown_at) {
+            Some(Escalation::Cooldown)
+        } else if reached(self.warn_at) {
+            Some(Escalation::Warn)
+        } else {
+            None
+        }
+    }
+
+    /// Whether `strikes` keep their owner from chatting at `now`.
+    pub fn restriction(&self, strikes: &[Strike], now: u64) -> Option<Restriction> {
+        if self.cooldown_at != 0 && strikes.len() >= self.cooldown_at {
+            let latest = strikes.iter().map(|s| s.at).max().unwrap_or(0);
+            let until = latest + self.cooldown.as_secs();
+            if until > now {
+                return Some(Restriction::Cooldown(until));
+            }
+        }
+        None
+    }
+
+    /// What someone who just got their `count`th strike, leaving them under
+    /// `restriction`, is told.
+    fn notice(&self, reason: &str, count: usize, restriction: Option<Restriction>) -> String {
+        let mut notice = format!("You received a strike: {reason}\nStrikes so far: {count}");
+        match restriction {
+            Some(Restriction::Cooldown(until)) => notice.push_str(&format!(
+                "\nYou cannot chat with strangers until <t:{until}:f>."
+            )),
+            None => {
+                if self.cooldown_at > count {
+                    notice.push_str(&format!(
+                        "\nAt {} strikes you will not be able to chat for a while.",
+                        self.cooldown_at
+                    ));
+                } else if self.ban_at > count {
+                    notice.push_str(&format!("\nAt {} strikes you will be banned.", self.ban_at));
+                }
+            }
+        }
+        notice
+    }
+}
+
+/// Records `strike`, tells its owner and acts on any threshold it reaches.
+/// Any strike that leaves its owner on cooldown, not just the one reaching
+/// `cooldown_at`, ends their chat.
+pub async fn add(ctx: &Context, strike: Strike) -> Result<(), GenericError> {
+    let policy = get_config(ctx).await.strike_policy.clone();
+    let sessions = get_sessions(ctx).await;
+    let count = sessions.add_strike(&strike).await?;
+    match policy.escalation(count) {
+        Some(Escalation::Ban) => {
+            let ban = Ban {
+                user: strike.user,
+                scope: BanScope::Global,
+                reason: Some(format!(
+                    "Reached {count} strikes, the last for: {}",
+                    strike.reason
+                )),
+                banned_by: strike
+                    .issued_by
+                    .unwrap_or_else(|| ctx.cache.current_user().id),
+                created_at: strike.at,
+                expires_at: None,
+            };
+            // Imposing the ban also tells them about it.
+            return bans::impose(ctx, &ban).await;
+        }
+        Some(Escalation::Cooldown | Escalation::Warn) | None => {}
+    }
+    let strikes = sessions.strikes(strike.user).await?;
+    let restriction = policy.restriction(&strikes, strike.at);
+    if restriction.is_some() {
+        if let Some(user) = sessions.load_user(strike.user).await? {
+            expel(ctx, &user, &*sessions).await?;
+        }
+    }
+    let notice = policy.notice(&strike.reason, count, restriction);
+    notify_user(ctx, strike.user, &notice).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strikes(times: &[u64]) -> Vec<Strike> {
+        times
+            .iter()
+            .map(|&at| Strike {
+                user: UserId::new(1),
+                reason: "spam".to_string(),
+                issued_by: None,
+                at,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn thresholds_escalate_and_bans_repeat() {
+        let policy = StrikePolicy::default();
+        let steps: Vec<Option<Escalation>> = (1..=6).map(|n| policy.escalation(n)).collect();
+        assert_eq!(
+            steps,
+            [
+                Some(Escalation::Warn),
+                None,
+                Some(Escalation::Cooldown),
+                None,
+                Some(Escalation::Ban),
+                Some(Escalation::Ban)
+            ]
+        );
+
+        let no_cooldown = StrikePolicy {
+            cooldown_at: 0,
+            ..StrikePolicy::default()
+        };
+        assert_eq!(no_cooldown.escalation(0), None);
+        assert_eq!(no_cooldown.escalation(3), None);
+    }
+
+    #[test]
+    fn cooldown_runs_from_the_latest_strike() {
+        let policy = StrikePolicy {
+            cooldown: Duration::from_secs(100),
+            ..StrikePolicy::default()
+        };
+        assert_eq!(policy.restriction(&strikes(&[1, 2]), 50), None);
+        let three = strikes(&[1, 30, 2]);
+        assert_eq!(
+            policy.restriction(&three, 50),
+            Some(Restriction::Cooldown(130))
+        );
+        assert_eq!(policy.restriction(&three, 130), None);
+        // Past the ban threshold the ban itself keeps them out.
+        assert_eq!(policy.restriction(&strikes(&[1, 2, 3, 4, 5]), 10_000), None);
+    }
+
+    #[test]
+    fn notices_say_what_comes_next() {
+        let policy = StrikePolicy::default();
+        assert_eq!(
+            policy.notice("spam", 1, None),
+            "You received a strike: spam\nStrikes so far: 1\nAt 3 strikes you will not be able to chat for a while."
+        );
+        assert!(policy
+            .notice("spam", 4, None)
+            .ends_with("At 5 strikes you will be banned."));
+        // Whichever strike leaves them on cooldown says until when.
+        assert!(policy
+            .notice("spam", 4, Some(Restriction::Cooldown(600)))
+            .ends_with("until <t:600:f>."));
+    }
+}
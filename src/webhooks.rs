@@ -0,0 +1,85 @@
+//! The webhooks relayed messages are posted through so they can carry the
+//! sender's persona. There is one per lobby channel, posting into the private
+//! threads under it.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use serenity::all::{ChannelId, Context, CreateWebhook, Webhook};
+use serenity::http::HttpError;
+
+use crate::error::GenericError;
+
+const WEBHOOK_NAME: &str = "Stranger relay";
+
+/// Discord's "Unknown Webhook" error code.
+const UNKNOWN_WEBHOOK: isize = 10015;
+
+/// How long a lobby where the bot may not manage webhooks is left alone
+/// before trying again.
+const RETRY_AFTER: Duration = Duration::from_secs(10 * 60);
+
+enum Cached {
+    Ready(Box<Webhook>),
+    Unavailable(Instant),
+}
+
+static WEBHOOKS: LazyLock<Mutex<HashMap<ChannelId, Cached>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn webhooks() -> std::sync::MutexGuard<'static, HashMap<ChannelId, Cached>> {
+    WEBHOOKS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The bot's webhook in `lobby`, set up on first use. `None` when the bot
+/// lacks Manage Webhooks there.
+pub async fn lobby_webhook(
+    ctx: &Context,
+    lobby: ChannelId,
+) -> Result<Option<Webhook>, GenericError> {
+    match webhooks().get(&lobby) {
+        Some(Cached::Ready(webhook)) => return Ok(Some((**webhook).clone())),
+        Some(Cached::Unavailable(since)) if since.elapsed() < RETRY_AFTER => return Ok(None),
+        _ => {}
+    }
+    match find_or_create(ctx, lobby).await {
+        Ok(webhook) => {
+            webhooks().insert(lobby, Cached::Ready(Box::new(webhook.clone())));
+            Ok(Some(webhook))
+        }
+        Err(GenericError::PermissionDenied(_)) => {
+            webhooks().insert(lobby, Cached::Unavailable(Instant::now()));
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+async fn find_or_create(ctx: &Context, lobby: ChannelId) -> Result<Webhook, GenericError> {
+    let bot = ctx.cache.current_user().id;
+    let existing = lobby.webhooks(&ctx.http).await?.into_iter().find(|w| {
+        w.name.as_deref() == Some(WEBHOOK_NAME)
+            && w.token.is_some()
+            && w.user.as_ref().is_some_and(|u| u.id == bot)
+    });
+    match existing {
+        Some(webhook) => Ok(webhook),
+        None => Ok(lobby
+            .create_webhook(&ctx.http, CreateWebhook::new(WEBHOOK_NAME))
+            .await?),
+    }
+}
+
+/// Drops the cached webhook of `lobby` if `error` says it no longer exists,
+/// e.g. because an admin deleted it. Returns whether it did.
+pub fn forget_if_gone(lobby: ChannelId, error: &serenity::Error) -> bool {
+    let gone = matches!(
+        error,
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(res)) if res.error.code == UNKNOWN_WEBHOOK
+    );
+    if gone {
+        webhooks().remove(&lobby);
+    }
+    gone
+}